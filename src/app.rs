@@ -1,6 +1,21 @@
+use crate::tray::Tray;
 use crate::ui;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, SystemTime};
+use timer_for_harvest::cache::{Cache, CachedAccount};
+use timer_for_harvest::idle::{Away, IdleMonitor};
+use timer_for_harvest::journal::{self, Change, Journal, PendingChange, Problem};
+use timer_for_harvest::login::LoginServer;
+use timer_for_harvest::service::{self, Service};
+use timer_for_harvest::session::{self, SessionMonitor};
+use timer_for_harvest::week::Week;
 use timer_for_harvest::*;
 
 pub enum Signal {
@@ -18,149 +33,1223 @@ pub enum Signal {
     MinutePassed,
     UpdateTimer(u32, u32, u32, String, f32),
     CheckVersion,
+    SignIn,
+    CancelLogin,
+    LoginFinished(Result<Authorization, HarvestError>),
+    SwitchAccount(u32),
+    UseAccessToken(String, u32),
+    SignOut,
+    ResolveConflict(u32, Resolution),
+    StopRunningTimers,
+    RestartLastTimer,
+    ToggleLastTimer,
+    BackFromIdle(Away),
+    ResolveIdle(u32, f32, IdleChoice),
+    Resumed(Away),
+    NetworkChanged(bool),
+    SetWeekView(bool),
+    /* from the week view */
+    ShowDay(chrono::NaiveDate),
+}
+
+/* what to do with an edit of a time entry that was changed in Harvest in the meantime */
+pub enum Resolution {
+    Merge,
+    Overwrite,
+    Discard,
+}
+
+/* what to do with the time a timer kept running while nobody was there */
+pub enum IdleChoice {
+    Keep,
+    Discard,
+    /* discarded, and booked as a new time entry instead */
+    Book,
+}
+
+/* an edit that was not saved, because the time entry was changed in Harvest since */
+struct Conflict {
+    account_id: u32,
+    base: TimeEntry,
+    mine: TimeEntry,
+    theirs: TimeEntry,
+}
+
+/* a sign in that is waiting for the browser in its own thread */
+struct Login {
+    cancelled: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+/* what is retrieved from Harvest separately for every account */
+#[derive(Default)]
+struct AccountData {
+    user: Option<User>,
+    project_assignments: Vec<ProjectAssignment>,
+    time_entries: Vec<TimeEntry>,
+    refreshed: bool,
 }
 
 pub struct App {
     to_ui: glib::Sender<ui::Signal>,
+    to_app: mpsc::Sender<Signal>,
     shown_date: chrono::NaiveDate,
-    api: Harvest,
-    user: User,
+    /* the whole week of shown_date instead of only that day */
+    week_view: bool,
+    api: Option<Harvest>,
+    login: Option<Login>,
+    user: Option<User>,
     project_assignments: Vec<ProjectAssignment>,
     time_entries: Vec<TimeEntry>,
+    /* those of the rest of the week in the week view */
+    other_days: Vec<TimeEntry>,
+    /* whether user and project_assignments were retrieved from Harvest instead of the cache */
+    refreshed: bool,
+    /* the user, project_assignments and time_entries above belong to the current account */
+    other_accounts: HashMap<u32, AccountData>,
+    /* set by show_error, the signal that caused it is replayed after signing in again */
+    auth_expired: Cell<bool>,
+    reauthorizing: bool,
+    paused: Vec<Signal>,
+    expiry_notice: Cell<Option<u64>>,
+    cache: Cache,
+    /* changes to time entries, shown right away and saved in Harvest when it can be reached */
+    journal: Journal,
+    /* shown one at a time, the first one is shown */
+    conflicts: Vec<Conflict>,
+    /* timer control on D-Bus, missing without a session bus */
+    service: Option<Service>,
+    tray: ksni::Handle<Tray>,
+    /* the end of the last time away that was asked about, idle and screen lock overlap */
+    asked_until: Option<SystemTime>,
+    network_available: bool,
+    /* retrieving time entries is put off until the network is up */
+    waiting_for_network: bool,
 }
 
 impl App {
-    pub fn new(to_ui: glib::Sender<ui::Signal>) -> App {
+    const EXPIRY_NOTICE_DAYS: u64 = 3;
+    const IDLE_MINUTES: u64 = 10;
+
+    pub fn new(to_ui: glib::Sender<ui::Signal>, to_app: mpsc::Sender<Signal>) -> App {
         let now = chrono::Local::today().naive_local();
-        let api = Harvest::new();
-        let user = api.current_user();
-        let mut project_assignments = api.active_project_assignments();
-        project_assignments.sort_by(|a, b| {
-            a.project
-                .name
-                .to_lowercase()
-                .cmp(&b.project.name.to_lowercase())
-        });
+        let tray = Tray::start(to_app.clone(), to_ui.clone());
 
         App {
             to_ui: to_ui,
+            to_app: to_app,
             shown_date: now,
-            api: api,
-            user: user,
-            project_assignments: project_assignments,
+            week_view: false,
+            api: None,
+            login: None,
+            user: None,
+            project_assignments: vec![],
             time_entries: vec![],
+            other_days: vec![],
+            refreshed: false,
+            other_accounts: HashMap::new(),
+            auth_expired: Cell::new(false),
+            reauthorizing: false,
+            paused: vec![],
+            expiry_notice: Cell::new(None),
+            cache: Cache::new(Cache::default_dir()),
+            journal: Journal::new(Journal::default_path()),
+            conflicts: vec![],
+            service: None,
+            tray: tray,
+            asked_until: None,
+            network_available: true,
+            waiting_for_network: false,
         }
     }
 
     pub fn handle_ui_signals(mut app: App, from_ui: mpsc::Receiver<Signal>) {
         thread::spawn(move || {
+            match Journal::load(Journal::default_path()) {
+                Ok(journal) => app.journal = journal,
+                Err(e) => app.show_error(e),
+            }
+            app.start_service();
+            app.start_idle_watch();
+            app.start_session_watch();
+            /* reading the keyring might block on an unlock prompt, so not done in App::new */
+            app.api = Harvest::from_environment()
+                .or_else(|| Harvest::from_store(&*credentials::default_store()));
+            if app.api.is_some() {
+                app.load_cached_account();
+                /* shows the cached time entries right away, then refreshes them */
+                app.retrieve_time_entries();
+                app.send_accounts();
+            } else {
+                app.sign_in();
+            }
+
+            if app.auth_expired.get() {
+                app.reauthorize(None);
+            }
+            app.check_expiry();
+
             for signal in from_ui {
-                match signal {
-                    Signal::RetrieveTimeEntries => {
-                        app.retrieve_time_entries();
-                    }
-                    Signal::NewTimeEntry => {
-                        app.to_ui
-                            .send(ui::Signal::OpenPopup(app.project_assignments.to_vec()))
-                            .expect("Sending message to ui thread");
-                    }
-                    Signal::EditTimeEntry(id) => {
-                        app.edit_time_entry(id);
-                    }
-                    Signal::RestartTimeEntry(id) => {
-                        app.restart_timer(id);
-                        app.retrieve_time_entries();
-                    }
-                    Signal::StopTimeEntry(id) => {
-                        app.stop_timer(id);
-                        app.retrieve_time_entries();
-                    }
-                    Signal::DeleteTimeEntry(id) => {
-                        app.api.delete_timer(id);
-                        app.retrieve_time_entries();
-                    }
-                    Signal::PrevDate => {
-                        app.shown_date = app.shown_date.pred();
-                        app.retrieve_time_entries();
-                    }
-                    Signal::NextDate => {
-                        app.shown_date = app.shown_date.succ();
-                        app.retrieve_time_entries();
-                    }
-                    Signal::TodayDate => {
-                        app.shown_date = chrono::Local::today().naive_local();
-                        app.retrieve_time_entries();
-                    }
-                    Signal::LoadTasksForProject(id) => {
-                        app.retrieve_tasks_for_project(id);
-                    }
-                    Signal::StartTimer(project_id, task_id, notes, hours) => {
-                        app.start_timer(project_id, task_id, notes, hours);
-                        app.retrieve_time_entries();
-                    }
-                    Signal::MinutePassed => {
-                        app.increment_running_timer();
+                app.process(signal);
+            }
+        });
+    }
+
+    fn process(&mut self, signal: Signal) {
+        match signal {
+            Signal::SignIn => {
+                self.sign_in();
+            }
+            Signal::CancelLogin => {
+                self.cancel_login();
+            }
+            Signal::LoginFinished(result) => {
+                self.login_finished(result);
+            }
+            Signal::UseAccessToken(token, account_id) => {
+                self.use_access_token(token, account_id);
+            }
+            Signal::CheckVersion => {
+                self.check_version();
+            }
+            Signal::NetworkChanged(available) => {
+                self.network_changed(available);
+            }
+            /* everything else needs Harvest, wait until signed in */
+            _ if self.api.is_none() => {}
+            /* replayed after signing in again */
+            signal if self.reauthorizing => {
+                self.pause(signal);
+            }
+            signal => {
+                self.handle(&signal);
+                if self.auth_expired.get() {
+                    self.reauthorize(Some(signal));
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, signal: &Signal) {
+        match signal {
+            Signal::RetrieveTimeEntries => {
+                if self.network_available && !self.waiting_for_network {
+                    self.retrieve_time_entries();
+                } else {
+                    self.waiting_for_network = true;
+                }
+            }
+            Signal::NewTimeEntry => {
+                self.to_ui
+                    .send(ui::Signal::OpenPopup(self.project_assignments.to_vec()))
+                    .expect("Sending message to ui thread");
+            }
+            Signal::EditTimeEntry(id) => {
+                self.edit_time_entry(*id);
+            }
+            Signal::RestartTimeEntry(id) => {
+                self.restart_timer(*id);
+                self.retrieve_time_entries();
+            }
+            Signal::StopTimeEntry(id) => {
+                self.stop_timer(*id);
+                self.retrieve_time_entries();
+            }
+            Signal::DeleteTimeEntry(id) => {
+                self.delete_timer(*id);
+                self.retrieve_time_entries();
+            }
+            Signal::PrevDate => {
+                self.shown_date = if self.week_view {
+                    self.shown_date - chrono::Duration::weeks(1)
+                } else {
+                    self.shown_date.pred()
+                };
+                self.retrieve_time_entries();
+            }
+            Signal::NextDate => {
+                self.shown_date = if self.week_view {
+                    self.shown_date + chrono::Duration::weeks(1)
+                } else {
+                    self.shown_date.succ()
+                };
+                self.retrieve_time_entries();
+            }
+            Signal::TodayDate => {
+                self.shown_date = chrono::Local::today().naive_local();
+                self.retrieve_time_entries();
+            }
+            Signal::LoadTasksForProject(id) => {
+                self.retrieve_tasks_for_project(*id);
+            }
+            Signal::StartTimer(project_id, task_id, notes, hours) => {
+                self.start_timer(*project_id, *task_id, notes.clone(), *hours);
+                self.retrieve_time_entries();
+            }
+            Signal::MinutePassed => {
+                self.update_running_hours();
+                self.check_expiry();
+                /* the network might have stayed up while asleep */
+                if self.waiting_for_network && self.network_available {
+                    self.network_changed(true);
+                }
+                /* Harvest might be reachable again */
+                if self.network_available && self.journal.has_changes(self.api().account_id()) {
+                    self.sync_changes();
+                    if !self.journal.has_changes(self.api().account_id()) {
+                        self.retrieve_time_entries();
                     }
-                    Signal::UpdateTimer(id, project_id, task_id, notes, hours) => {
-                        app.update_timer(id, project_id, task_id, notes, hours);
-                        app.retrieve_time_entries();
+                }
+            }
+            Signal::UpdateTimer(id, project_id, task_id, notes, hours) => {
+                self.update_timer(*id, *project_id, *task_id, notes.clone(), *hours);
+                self.retrieve_time_entries();
+            }
+            Signal::SwitchAccount(id) => {
+                self.switch_account(*id);
+            }
+            Signal::SignOut => {
+                self.sign_out();
+            }
+            Signal::ResolveConflict(id, resolution) => {
+                self.resolve_conflict(*id, resolution);
+                self.retrieve_time_entries();
+            }
+            Signal::StopRunningTimers => {
+                self.show_today();
+                self.stop_running_timers();
+                self.retrieve_time_entries();
+            }
+            Signal::RestartLastTimer => {
+                self.show_today();
+                self.restart_last_timer();
+                self.retrieve_time_entries();
+            }
+            Signal::ToggleLastTimer => {
+                self.show_today();
+                self.toggle_last_timer();
+                self.retrieve_time_entries();
+            }
+            Signal::BackFromIdle(away) => {
+                self.ask_about_idle_time(away);
+            }
+            Signal::ResolveIdle(id, hours, choice) => {
+                self.resolve_idle(*id, *hours, choice);
+                self.retrieve_time_entries();
+            }
+            Signal::Resumed(away) => {
+                /* reconnecting to the network takes a while */
+                self.waiting_for_network = true;
+                if env::var("TFH_STOP_ON_SUSPEND").is_ok() {
+                    self.stop_after_sleep(away);
+                    self.show_cached_time_entries();
+                } else {
+                    self.ask_about_idle_time(away);
+                }
+            }
+            Signal::SetWeekView(week_view) => {
+                /* the toggle in the window follows the view, which sends it back */
+                if self.week_view != *week_view {
+                    self.week_view = *week_view;
+                    self.retrieve_time_entries();
+                }
+            }
+            Signal::ShowDay(date) => {
+                self.week_view = false;
+                self.shown_date = *date;
+                self.retrieve_time_entries();
+            }
+            /* see process */
+            Signal::SignIn
+            | Signal::CancelLogin
+            | Signal::LoginFinished(_)
+            | Signal::UseAccessToken(_, _)
+            | Signal::CheckVersion
+            | Signal::NetworkChanged(_) => {}
+        }
+    }
+
+    /* Harvest rejected the token, pause until signed in again and then retry what failed */
+    fn reauthorize(&mut self, failed: Option<Signal>) {
+        self.auth_expired.set(false);
+
+        if let Some(signal) = failed {
+            let signal = match signal {
+                /* the date already changed, only retrieving its time entries failed */
+                Signal::PrevDate
+                | Signal::NextDate
+                | Signal::TodayDate
+                | Signal::SetWeekView(_)
+                | Signal::ShowDay(_) => Signal::RetrieveTimeEntries,
+                /* already recorded, saving it in Harvest is retried when retrieving */
+                Signal::StartTimer(_, _, _, _)
+                | Signal::RestartTimeEntry(_)
+                | Signal::StopTimeEntry(_)
+                | Signal::UpdateTimer(_, _, _, _, _)
+                | Signal::ResolveConflict(_, _)
+                | Signal::StopRunningTimers
+                | Signal::RestartLastTimer
+                | Signal::ToggleLastTimer
+                | Signal::ResolveIdle(_, _, _)
+                | Signal::Resumed(_) => Signal::RetrieveTimeEntries,
+                signal => signal,
+            };
+            self.pause(signal);
+        }
+        if !self.reauthorizing {
+            self.reauthorizing = true;
+            self.sign_in();
+        }
+    }
+
+    /* the sign in window might stay open for a long time, so without signals that pile up */
+    fn pause(&mut self, signal: Signal) {
+        match signal {
+            /* the time entries are retrieved again afterwards anyway */
+            Signal::MinutePassed => {}
+            Signal::RetrieveTimeEntries
+                if self
+                    .paused
+                    .iter()
+                    .any(|s| matches!(s, Signal::RetrieveTimeEntries)) => {}
+            signal => self.paused.push(signal),
+        }
+    }
+
+    /* there is no refresh token, so a new one is obtained by signing in again */
+    fn check_expiry(&self) {
+        let days = match self.api.as_ref() {
+            Some(api) => api.authorization().expires_in_days(),
+            None => None,
+        };
+
+        if let Some(days) = days {
+            if days < App::EXPIRY_NOTICE_DAYS && self.expiry_notice.get() != Some(days) {
+                self.expiry_notice.set(Some(days));
+                let when = match days {
+                    0 => "today".to_string(),
+                    1 => "tomorrow".to_string(),
+                    days => format!("in {} days", days),
+                };
+                self.to_ui
+                    .send(ui::Signal::ShowNotice(format!(
+                        "Harvest authorization expires {}, <a href=\"{}\">sign in again</a>",
+                        when,
+                        ui::SIGN_IN_LINK
+                    )))
+                    .expect("Sending message to ui thread");
+            }
+        }
+    }
+
+    fn api(&self) -> &Harvest {
+        self.api.as_ref().expect("Signed in to Harvest")
+    }
+
+    fn sign_in(&mut self) {
+        self.stop_login();
+
+        let server = match LoginServer::bind() {
+            Ok(server) => server,
+            Err(e) => {
+                self.to_ui
+                    .send(ui::Signal::LoginFailed(e.to_string()))
+                    .expect("Sending message to ui thread");
+                return;
+            }
+        };
+        self.to_ui
+            .send(ui::Signal::ShowLogin(server.authorize_url()))
+            .expect("Sending message to ui thread");
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let to_app = self.to_app.clone();
+        let thread = thread::spawn({
+            let cancelled = cancelled.clone();
+            move || {
+                let result = server.wait(LoginServer::TIMEOUT, &cancelled);
+                /* nobody is waiting for the result of a stopped login */
+                if !cancelled.load(Ordering::SeqCst) {
+                    to_app
+                        .send(Signal::LoginFinished(result))
+                        .expect("Sending message to application thread");
+                }
+            }
+        });
+        self.login = Some(Login {
+            cancelled: cancelled,
+            thread: thread,
+        });
+    }
+
+    /* waits for the thread, so its port is free again for the next attempt */
+    fn stop_login(&mut self) {
+        if let Some(login) = self.login.take() {
+            login.cancelled.store(true, Ordering::SeqCst);
+            let _ = login.thread.join();
+        }
+    }
+
+    fn cancel_login(&mut self) {
+        self.stop_login();
+        self.to_ui
+            .send(ui::Signal::HideLogin)
+            .expect("Sending message to ui thread");
+
+        /* there is nothing to show without access to Harvest */
+        if self.api.is_none() {
+            self.to_ui
+                .send(ui::Signal::Quit)
+                .expect("Sending message to ui thread");
+        } else if self.reauthorizing {
+            /* the next request asks to sign in again */
+            self.reauthorizing = false;
+            self.paused.clear();
+            self.show_error(HarvestError::Login(
+                "not signed in, changes are not saved".to_string(),
+            ));
+        }
+    }
+
+    /* only replaces signing in using the browser once Harvest accepted the token */
+    fn use_access_token(&mut self, token: String, account_id: u32) {
+        let authorization = Authorization::personal_access_token(token, account_id);
+
+        match Harvest::from_authorization(authorization.clone()).current_user() {
+            Ok(_) => {
+                self.stop_login();
+                self.login_finished(Ok(authorization));
+            }
+            Err(e) => {
+                let message = match e {
+                    HarvestError::AuthExpired => "Harvest did not accept this token".to_string(),
+                    e => e.to_string(),
+                };
+                self.to_ui
+                    .send(ui::Signal::AccessTokenFailed(message))
+                    .expect("Sending message to ui thread");
+            }
+        }
+    }
+
+    fn login_finished(&mut self, result: Result<Authorization, HarvestError>) {
+        self.login = None;
+
+        match result {
+            Ok(mut authorization) => {
+                /* signing in again should not change the account in use */
+                if let Some(api) = &self.api {
+                    if authorization.account_ids.contains(&api.account_id()) {
+                        authorization.account_id = api.account_id();
                     }
-                    Signal::CheckVersion => {
-                        app.check_version();
+                }
+                /* failing to remember it only means signing in again on the next launch */
+                if let Err(e) = credentials::default_store().store(&authorization) {
+                    self.show_error(e);
+                }
+                self.api = Some(Harvest::from_authorization(authorization));
+                self.load_cached_account();
+                self.other_accounts.clear();
+                self.expiry_notice.set(None);
+                self.to_ui
+                    .send(ui::Signal::HideLogin)
+                    .expect("Sending message to ui thread");
+                self.send_accounts();
+
+                self.reauthorizing = false;
+                let paused = mem::take(&mut self.paused);
+                if paused.is_empty() {
+                    self.retrieve_time_entries();
+                } else {
+                    for signal in paused {
+                        self.process(signal);
                     }
                 }
             }
+            Err(e) => {
+                self.to_ui
+                    .send(ui::Signal::LoginFailed(e.to_string()))
+                    .expect("Sending message to ui thread");
+            }
+        }
+    }
+
+    /* forget everything about the signed in user and start over */
+    fn sign_out(&mut self) {
+        if let Err(e) = credentials::clear_all() {
+            self.show_error(e);
+        }
+
+        if let Err(e) = self.journal.clear() {
+            self.show_error(e);
+        }
+        if let Err(e) = self.cache.clear() {
+            self.to_ui
+                .send(ui::Signal::ShowNotice(escape_html(&format!(
+                    "Unable to remove cached data: {}",
+                    e
+                ))))
+                .expect("Sending message to ui thread");
+        }
+
+        self.api = None;
+        self.user = None;
+        self.project_assignments.clear();
+        self.time_entries.clear();
+        self.other_days.clear();
+        self.refreshed = false;
+        self.other_accounts.clear();
+        self.reauthorizing = false;
+        self.paused.clear();
+        self.expiry_notice.set(None);
+        self.conflicts.clear();
+        if let Some(service) = &self.service {
+            service.set_running(None);
+        }
+        self.tray.update(|tray| tray.set_time_entries(vec![]));
+
+        self.to_ui
+            .send(ui::Signal::SetAccounts(vec![], 0))
+            .expect("Sending message to ui thread");
+        self.to_ui
+            .send(ui::Signal::SetTimeEntries(vec![], vec![]))
+            .expect("Sending message to ui thread");
+        self.to_ui
+            .send(ui::Signal::SetTitle("Harvest".to_string()))
+            .expect("Sending message to ui thread");
+        self.sign_in();
+    }
+
+    fn send_accounts(&self) {
+        let accounts = match self.api().accounts() {
+            Ok(accounts) => accounts,
+            /* the switcher is still usable without the names */
+            Err(_) => self
+                .api()
+                .authorization()
+                .account_ids()
+                .iter()
+                .map(|id| Account {
+                    id: *id,
+                    name: format!("Account {}", id),
+                    product: "harvest".to_string(),
+                })
+                .collect(),
+        };
+
+        self.to_ui
+            .send(ui::Signal::SetAccounts(accounts, self.api().account_id()))
+            .expect("Sending message to ui thread");
+    }
+
+    fn switch_account(&mut self, id: u32) {
+        let current_id = self.api().account_id();
+        if id == current_id || !self.api().authorization().account_ids().contains(&id) {
+            return;
+        }
+
+        let current = AccountData {
+            user: self.user.take(),
+            project_assignments: mem::take(&mut self.project_assignments),
+            time_entries: mem::take(&mut self.time_entries),
+            refreshed: self.refreshed,
+        };
+        self.other_accounts.insert(current_id, current);
+
+        let api = self.api.as_mut().expect("Signed in to Harvest");
+        api.switch_account(id);
+        /* use the same account on the next launch */
+        if let Err(e) = credentials::default_store().store(api.authorization()) {
+            self.show_error(e);
+        }
+
+        match self.other_accounts.remove(&id) {
+            Some(data) => {
+                self.user = data.user;
+                self.project_assignments = data.project_assignments;
+                self.time_entries = data.time_entries;
+                self.refreshed = data.refreshed;
+            }
+            None => self.load_cached_account(),
+        }
+        self.retrieve_time_entries();
+    }
+
+    /* what Harvest returned last time for the current account, refreshed later on */
+    fn load_cached_account(&mut self) {
+        match self.cache.account(self.api().account_id()) {
+            Some(account) => {
+                self.user = Some(account.user);
+                self.project_assignments = account.project_assignments;
+            }
+            None => {
+                self.user = None;
+                self.project_assignments = vec![];
+            }
+        }
+        self.refreshed = false;
+    }
+
+    fn show_today(&mut self) {
+        let today = chrono::Local::today().naive_local();
+        if self.shown_date != today {
+            self.shown_date = today;
+            self.retrieve_time_entries();
+        }
+    }
+
+    /* fails in a second instance, which only activates the first one through GTK */
+    fn start_service(&mut self) {
+        let to_app = self.to_app.clone();
+        let result = Service::start(None, move |request| {
+            let signals = match request {
+                /* timers are started on the shown day, which might not be today */
+                service::Request::StartTimer(project_id, task_id, notes) => vec![
+                    Signal::TodayDate,
+                    Signal::StartTimer(project_id, task_id, notes, 0.0),
+                ],
+                service::Request::StopRunning => vec![Signal::StopRunningTimers],
+                service::Request::ToggleLast => vec![Signal::ToggleLastTimer],
+            };
+            for signal in signals {
+                to_app
+                    .send(signal)
+                    .expect("Sending message to application thread");
+            }
         });
+
+        match result {
+            Ok(service) => self.service = Some(service),
+            /* everything else works without it */
+            Err(e) => eprintln!("Unable to start D-Bus service: {}", e),
+        }
+    }
+
+    /* minutes without input before asking, TFH_IDLE_MINUTES=0 turns it off */
+    fn start_idle_watch(&self) {
+        let minutes: u64 = env::var("TFH_IDLE_MINUTES")
+            .ok()
+            .and_then(|m| m.parse().ok())
+            .unwrap_or(App::IDLE_MINUTES);
+        if minutes == 0 {
+            return;
+        }
+
+        match IdleMonitor::connect(None) {
+            Ok(monitor) => {
+                let to_app = self.to_app.clone();
+                monitor.watch(
+                    Duration::from_secs(minutes * 60),
+                    IdleMonitor::POLL_INTERVAL,
+                    move |away| {
+                        to_app
+                            .send(Signal::BackFromIdle(away))
+                            .expect("Sending message to application thread");
+                    },
+                );
+            }
+            /* everything else works without it */
+            Err(e) => eprintln!("Unable to detect idle time: {}", e),
+        }
+    }
+
+    /* TFH_STOP_ON_LOCK stops timers when the screen locks instead of asking once unlocked */
+    fn start_session_watch(&self) {
+        let stop_on_lock = env::var("TFH_STOP_ON_LOCK").is_ok();
+        match SessionMonitor::connect(None) {
+            Ok(monitor) => {
+                let to_app = self.to_app.clone();
+                monitor.watch(move |event| {
+                    let signal = match event {
+                        session::Event::Locked if stop_on_lock => Signal::StopRunningTimers,
+                        session::Event::Unlocked(away) if !stop_on_lock => {
+                            Signal::BackFromIdle(away)
+                        }
+                        session::Event::Resumed(away) => Signal::Resumed(away),
+                        _ => return,
+                    };
+                    to_app
+                        .send(signal)
+                        .expect("Sending message to application thread");
+                });
+            }
+            /* everything else works without it */
+            Err(e) => eprintln!("Unable to detect suspend and screen locks: {}", e),
+        }
+    }
+
+    fn network_changed(&mut self, available: bool) {
+        self.network_available = available;
+        if available && self.waiting_for_network {
+            self.waiting_for_network = false;
+            if self.api.is_some() && !self.reauthorizing {
+                self.retrieve_time_entries();
+            }
+        }
     }
 
     fn format_and_send_title(&self) {
-        let title = format!("Harvest - {}", self.shown_date.format("%a %-d %b"));
+        let title = if self.week_view {
+            let days = Week::days_of(self.shown_date);
+            format!(
+                "Harvest - {} - {}",
+                days[0].format("%-d %b"),
+                days[days.len() - 1].format("%-d %b")
+            )
+        } else {
+            format!("Harvest - {}", self.shown_date.format("%a %-d %b"))
+        };
         self.to_ui
             .send(ui::Signal::SetTitle(title))
             .expect("Sending message to ui thread");
     }
 
-    fn retrieve_time_entries(&mut self) {
+    fn show_error(&self, error: HarvestError) {
+        /* not worth a notice, signing in again is asked for instead */
+        if let HarvestError::AuthExpired = error {
+            self.auth_expired.set(true);
+            return;
+        }
+
         self.to_ui
-            .send(ui::Signal::SetTitle("Loading...".to_string()))
+            .send(ui::Signal::ShowNotice(escape_html(&error.to_string())))
             .expect("Sending message to ui thread");
-        self.time_entries = self.api.time_entries_for(
-            &self.user,
-            self.shown_date.to_string(),
-            self.shown_date.to_string(),
+    }
+
+    /* only needed once, but retried on refresh when it failed at startup */
+    fn retrieve_user_and_project_assignments(&mut self) -> Result<(), HarvestError> {
+        if self.refreshed {
+            return Ok(());
+        }
+
+        let user = self.api().current_user()?;
+        let mut project_assignments = self.api().active_project_assignments()?;
+        project_assignments.sort_by(|a, b| {
+            a.project
+                .name
+                .to_lowercase()
+                .cmp(&b.project.name.to_lowercase())
+        });
+
+        /* the cache only makes startup faster, it is fine when it can not be written */
+        let _ = self.cache.store_account(
+            self.api().account_id(),
+            &CachedAccount {
+                user: user.clone(),
+                project_assignments: project_assignments.clone(),
+            },
         );
+        self.user = Some(user);
+        self.project_assignments = project_assignments;
+        self.refreshed = true;
+        Ok(())
+    }
 
+    fn retrieve_time_entries(&mut self) {
+        self.show_cached_time_entries();
+        /* trying would only wait for the retries to run out, it is done when the network is back */
+        if !self.network_available {
+            self.waiting_for_network = true;
+            self.format_and_send_title();
+            return;
+        }
         self.to_ui
-            .send(ui::Signal::SetTimeEntries(self.time_entries.clone()))
+            .send(ui::Signal::SetTitle("Loading...".to_string()))
             .expect("Sending message to ui thread");
+
+        self.sync_changes();
+        let days = self.shown_days();
+        let result = match self.retrieve_user_and_project_assignments() {
+            Ok(()) => self.api().time_entries_for(
+                self.user.as_ref().unwrap(),
+                days[0].to_string(),
+                days[days.len() - 1].to_string(),
+            ),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(time_entries) => {
+                let account_id = self.api().account_id();
+                self.other_days.clear();
+                /* cached per day, so the week and its days are shown right away later on */
+                for day in days {
+                    let mut of_day: Vec<TimeEntry> = time_entries
+                        .iter()
+                        .filter(|t| t.spent_date == day.to_string())
+                        .cloned()
+                        .collect();
+                    let _ = self.cache.store_time_entries(account_id, &day, &of_day);
+                    self.journal
+                        .apply(account_id, &day.to_string(), &mut of_day);
+                    if day == self.shown_date {
+                        self.time_entries = of_day;
+                    } else {
+                        self.other_days.append(&mut of_day);
+                    }
+                }
+            }
+            Err(e) => self.show_error(e),
+        }
+
+        self.send_time_entries();
         self.format_and_send_title();
     }
 
-    fn increment_running_timer(&mut self) {
-        for mut time_entry in &mut self.time_entries {
-            if time_entry.is_running {
-                time_entry.hours += 1.0 / 60.0;
+    /* shown while waiting for Harvest, or instead of it when it can not be reached */
+    fn show_cached_time_entries(&mut self) {
+        let account_id = self.api().account_id();
+        self.time_entries = self
+            .cache
+            .time_entries(account_id, &self.shown_date)
+            .unwrap_or_default();
+        self.apply_changes();
+
+        self.other_days.clear();
+        for day in self.shown_days() {
+            if day == self.shown_date {
+                continue;
             }
+            let mut of_day = self
+                .cache
+                .time_entries(account_id, &day)
+                .unwrap_or_default();
+            self.journal
+                .apply(account_id, &day.to_string(), &mut of_day);
+            self.other_days.append(&mut of_day);
         }
+        self.send_time_entries();
+    }
 
-        self.to_ui
-            .send(ui::Signal::SetTimeEntries(self.time_entries.clone()))
-            .expect("Sending message to ui thread");
-        self.format_and_send_title();
+    fn shown_days(&self) -> Vec<chrono::NaiveDate> {
+        if self.week_view {
+            Week::days_of(self.shown_date)
+        } else {
+            vec![self.shown_date]
+        }
+    }
+
+    fn send_time_entries(&self) {
+        if self.week_view {
+            let mut time_entries = self.other_days.clone();
+            time_entries.extend(self.time_entries.iter().cloned());
+            self.to_ui
+                .send(ui::Signal::SetWeek(Week::new(
+                    self.shown_date,
+                    &time_entries,
+                )))
+                .expect("Sending message to ui thread");
+        } else {
+            self.to_ui
+                .send(ui::Signal::SetTimeEntries(
+                    self.time_entries.clone(),
+                    self.journal.pending(self.api().account_id()),
+                ))
+                .expect("Sending message to ui thread");
+        }
+
+        /* a running timer is always one of today */
+        if self.shown_date == chrono::Local::today().naive_local() {
+            if let Some(service) = &self.service {
+                service.set_running(self.time_entries.iter().find(|t| t.is_running));
+            }
+            let time_entries = self.time_entries.clone();
+            self.tray
+                .update(move |tray| tray.set_time_entries(time_entries));
+        }
     }
 
-    fn restart_timer(&self, id: u32) {
+    fn apply_changes(&mut self) {
+        self.journal.apply(
+            self.api().account_id(),
+            &self.shown_date.to_string(),
+            &mut self.time_entries,
+        );
+    }
+
+    /* they stay pending while Harvest can not be reached */
+    fn sync_changes(&mut self) {
+        let api = self.api.as_ref().expect("Signed in to Harvest");
+        if !self.journal.has_changes(api.account_id()) {
+            return;
+        }
+
+        match self.journal.sync(api) {
+            Ok(problems) => {
+                for problem in problems {
+                    match problem {
+                        /* the user decides, edits are easily lost otherwise */
+                        Problem::Conflict(
+                            PendingChange {
+                                account_id,
+                                change: Change::Update(mine),
+                                base: Some(base),
+                                ..
+                            },
+                            Some(theirs),
+                        ) => self.add_conflict(Conflict {
+                            account_id: account_id,
+                            base: base,
+                            mine: mine,
+                            theirs: *theirs,
+                        }),
+                        problem => self.report_problem(problem),
+                    }
+                }
+            }
+            /* still pending, tried again later */
+            Err(HarvestError::Transport(_)) => {}
+            Err(HarvestError::Status(status, _)) if status == 429 || status >= 500 => {}
+            Err(e) => self.show_error(e),
+        }
+    }
+
+    fn add_conflict(&mut self, conflict: Conflict) {
+        self.conflicts.push(conflict);
+        if self.conflicts.len() == 1 {
+            self.show_conflict();
+        }
+    }
+
+    fn show_conflict(&self) {
+        if let Some(conflict) = self.conflicts.first() {
+            self.to_ui
+                .send(ui::Signal::ShowConflict(
+                    self.project_assignments.to_vec(),
+                    conflict.mine.clone(),
+                    conflict.theirs.clone(),
+                ))
+                .expect("Sending message to ui thread");
+        }
+    }
+
+    fn resolve_conflict(&mut self, id: u32, resolution: &Resolution) {
+        let index = match self.conflicts.iter().position(|c| c.mine.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let conflict = self.conflicts.remove(index);
+
+        let edited = match resolution {
+            Resolution::Merge => Some(journal::merge(
+                &conflict.base,
+                &conflict.mine,
+                &conflict.theirs,
+            )),
+            Resolution::Overwrite => Some(conflict.mine),
+            Resolution::Discard => None,
+        };
+        if let Some(edited) = edited {
+            /* based on what Harvest has now, so it is saved unless changed there again */
+            if let Err(e) = self.journal.record(
+                conflict.account_id,
+                Change::Update(edited),
+                Some(&conflict.theirs),
+            ) {
+                self.show_error(e);
+            }
+        }
+        self.show_conflict();
+    }
+
+    /* only when a timer of today kept running, and once for overlapping times away */
+    fn ask_about_idle_time(&mut self, away: &Away) {
+        if self.asked_until.map_or(false, |until| away.since < until) {
+            return;
+        }
+        /* without the network the timer is looked for in the shown day */
+        if !self.waiting_for_network {
+            self.show_today();
+        }
+        let mut running = match self.time_entries.iter().find(|t| t.is_running) {
+            Some(running) => running.clone(),
+            None => return,
+        };
+
+        let now = chrono::Utc::now();
+        let hours = away.hours_of(&running, now);
+        running.hours = running.hours_at(now);
+        let since = chrono::DateTime::<chrono::Local>::from(away.since)
+            .format("%H:%M")
+            .to_string();
         self.to_ui
-            .send(ui::Signal::SetTitle("Loading...".to_string()))
+            .send(ui::Signal::ShowIdle(running, hours, since))
             .expect("Sending message to ui thread");
-        self.api.restart_timer(id);
+        self.asked_until = Some(away.since + away.duration);
+    }
+
+    /* as if the timers were stopped when falling asleep */
+    fn stop_after_sleep(&mut self, away: &Away) {
+        let now = chrono::Utc::now();
+        let running: Vec<(u32, f32)> = self
+            .time_entries
+            .iter()
+            .filter(|t| t.is_running)
+            .map(|t| (t.id, away.hours_of(t, now)))
+            .collect();
+        for (id, hours) in running {
+            self.subtract_hours(id, hours, false);
+        }
+        self.asked_until = Some(away.since + away.duration);
+    }
+
+    fn resolve_idle(&mut self, id: u32, hours: f32, choice: &IdleChoice) {
+        match choice {
+            IdleChoice::Keep => {}
+            IdleChoice::Discard => self.subtract_hours(id, hours, true),
+            IdleChoice::Book => {
+                self.subtract_hours(id, hours, true);
+                self.to_ui
+                    .send(ui::Signal::OpenPopupWithHours(
+                        self.project_assignments.to_vec(),
+                        hours,
+                    ))
+                    .expect("Sending message to ui thread");
+            }
+        }
+    }
+
+    /* Harvest counts the hours of running timers, so stopped while changing them */
+    fn subtract_hours(&mut self, id: u32, hours: f32, keep_running: bool) {
+        let (edited, was_running) = match self.time_entries.iter().find(|t| t.id == id) {
+            Some(time_entry) => (
+                time_entry.stopped_without(hours, chrono::Utc::now()),
+                time_entry.is_running,
+            ),
+            None => return,
+        };
+
+        if was_running {
+            self.stop_timer(id);
+        }
+        self.record(Change::Update(edited));
+        if was_running && keep_running {
+            self.record(Change::Restart(id));
+        }
     }
 
-    fn stop_timer(&self, id: u32) {
+    fn report_problem(&self, problem: Problem) {
+        let message = match problem {
+            Problem::Conflict(pending, Some(_)) => format!(
+                "Your change to {} was not saved, it was changed in Harvest in the meantime",
+                App::describe(&pending)
+            ),
+            Problem::Conflict(pending, None) => format!(
+                "Your change to {} was not saved, it was deleted in Harvest in the meantime",
+                App::describe(&pending)
+            ),
+            /* see Journal::replay */
+            Problem::Rejected(
+                pending @ PendingChange {
+                    change: Change::Start(_),
+                    ..
+                },
+                HarvestError::Transport(e),
+            ) => format!(
+                "The connection broke while saving {}, check in Harvest whether it was saved: {}",
+                App::describe(&pending),
+                e
+            ),
+            Problem::Rejected(pending, e) => format!(
+                "Your change to {} was not saved: {}",
+                App::describe(&pending),
+                e
+            ),
+        };
         self.to_ui
-            .send(ui::Signal::SetTitle("Loading...".to_string()))
+            .send(ui::Signal::ShowNotice(escape_html(&message)))
             .expect("Sending message to ui thread");
-        self.api.stop_timer(id);
+    }
+
+    fn describe(pending: &PendingChange) -> String {
+        match pending.time_entry() {
+            Some(time_entry) => format!(
+                "the time entry for {} ({})",
+                time_entry.project.name, time_entry.task.name
+            ),
+            None => "a time entry".to_string(),
+        }
+    }
+
+    /* shown is the time entry the change is made to as it is currently shown */
+    fn record(&mut self, change: Change) {
+        let id = change.time_entry_id();
+        let shown = self.time_entries.iter().find(|t| t.id == id).cloned();
+        let account_id = self.api().account_id();
+        if let Err(e) = self.journal.record(account_id, change, shown.as_ref()) {
+            self.show_error(e);
+        }
+    }
+
+    /* like Harvest does when starting a timer, but recorded to keep the hours at this moment */
+    fn stop_running_timers(&mut self) {
+        let running: Vec<u32> = self
+            .time_entries
+            .iter()
+            .filter(|t| t.is_running)
+            .map(|t| t.id)
+            .collect();
+        for id in running {
+            self.record(Change::Stop(id));
+        }
+    }
+
+    /* the one changed last, unless a timer is running already */
+    fn restart_last_timer(&mut self) {
+        if self.time_entries.iter().any(|t| t.is_running) {
+            return;
+        }
+
+        let last = self
+            .time_entries
+            .iter()
+            .max_by(|a, b| a.updated_at.cmp(&b.updated_at))
+            .map(|t| t.id);
+        if let Some(id) = last {
+            self.restart_timer(id);
+        }
+    }
+
+    fn toggle_last_timer(&mut self) {
+        if self.time_entries.iter().any(|t| t.is_running) {
+            self.stop_running_timers();
+        } else {
+            self.restart_last_timer();
+        }
+    }
+
+    /* the window counts along by itself, this keeps the tray and D-Bus service up to date */
+    fn update_running_hours(&mut self) {
+        let now = chrono::Utc::now();
+        for time_entry in self.time_entries.iter_mut().chain(&mut self.other_days) {
+            time_entry.hours = time_entry.hours_at(now);
+        }
+
+        self.send_time_entries();
+        self.format_and_send_title();
+    }
+
+    fn restart_timer(&mut self, id: u32) {
+        self.stop_running_timers();
+        self.record(Change::Restart(id));
+    }
+
+    fn stop_timer(&mut self, id: u32) {
+        self.record(Change::Stop(id));
+    }
+
+    /* not recorded, deleting can not be undone when it turns out to conflict */
+    fn delete_timer(&mut self, id: u32) {
+        let account_id = self.api().account_id();
+        let result = if self.journal.is_local(account_id, id) {
+            Ok(())
+        } else {
+            self.api().delete_timer(id).map(|_| ())
+        };
+
+        match result.and_then(|()| self.journal.forget(account_id, id)) {
+            Ok(()) => {}
+            Err(e) => self.show_error(e),
+        }
     }
 
     fn retrieve_tasks_for_project(&self, id: u32) {
@@ -176,26 +1265,62 @@ impl App {
         }
     }
 
-    fn start_timer(&self, project_id: u32, task_id: u32, notes: String, hours: f32) {
-        self.api
-            .start_timer(project_id, task_id, notes, hours, &self.shown_date);
+    /* the project and task as assigned to the user */
+    fn find_assignment(&self, project_id: u32, task_id: u32) -> Option<(&ProjectAssignment, Task)> {
+        let project_assignment = self
+            .project_assignments
+            .iter()
+            .find(|p| p.project.id == project_id)?;
+        let task_assignment = project_assignment
+            .task_assignments
+            .iter()
+            .find(|t| t.task.id == task_id)?;
+        Some((project_assignment, task_assignment.task.clone()))
     }
 
-    fn update_timer(&self, id: u32, project_id: u32, task_id: u32, notes: String, hours: f32) {
-        for time_entry in &self.time_entries {
-            if time_entry.id == id {
-                self.api.update_timer(
-                    id,
-                    project_id,
-                    task_id,
-                    notes,
-                    hours,
-                    time_entry.is_running,
-                    time_entry.spent_date.clone(),
-                );
-                break;
+    fn start_timer(&mut self, project_id: u32, task_id: u32, notes: String, hours: f32) {
+        let time_entry = match (self.find_assignment(project_id, task_id), &self.user) {
+            (Some((project_assignment, task)), Some(user)) => TimeEntry {
+                id: 0,
+                project: project_assignment.project.clone(),
+                client: project_assignment.client.clone(),
+                hours: hours,
+                user: user.clone(),
+                spent_date: self.shown_date.to_string(),
+                task: task,
+                notes: if notes.is_empty() { None } else { Some(notes) },
+                /* like Harvest, time entries with hours are not started */
+                is_running: hours == 0.0,
+                updated_at: None,
+                timer_started_at: None,
+                hours_without_timer: None,
+            },
+            _ => return,
+        };
+
+        if time_entry.is_running {
+            self.stop_running_timers();
+        }
+        self.record(Change::Start(time_entry));
+    }
+
+    fn update_timer(&mut self, id: u32, project_id: u32, task_id: u32, notes: String, hours: f32) {
+        let mut edited = match self.time_entries.iter().find(|t| t.id == id) {
+            Some(time_entry) => time_entry.clone(),
+            None => return,
+        };
+        match self.find_assignment(project_id, task_id) {
+            Some((project_assignment, task)) => {
+                edited.project = project_assignment.project.clone();
+                edited.client = project_assignment.client.clone();
+                edited.task = task;
             }
+            None => return,
         }
+        edited.notes = Some(notes);
+        edited.hours = hours;
+
+        self.record(Change::Update(edited));
     }
 
     fn edit_time_entry(&self, id: u32) {
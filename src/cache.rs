@@ -0,0 +1,94 @@
+use crate::{write_private_json, ProjectAssignment, TimeEntry, User};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/* what Harvest returned last time, shown at startup and when Harvest can not be reached */
+pub struct Cache {
+    dir: PathBuf,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CachedAccount {
+    pub user: User,
+    pub project_assignments: Vec<ProjectAssignment>,
+}
+
+impl Cache {
+    /* days of time entries kept, the least recently viewed ones are removed first */
+    const KEEP_DAYS: usize = 31;
+
+    pub fn new(dir: PathBuf) -> Cache {
+        Cache { dir: dir }
+    }
+
+    pub fn default_dir() -> PathBuf {
+        let mut path = dirs::cache_dir().expect("Unable to find XDG cache dir path");
+        path.push("timer-for-harvest");
+        path
+    }
+
+    pub fn account(&self, account_id: u32) -> Option<CachedAccount> {
+        Cache::load(self.account_dir(account_id).join("account.json"))
+    }
+
+    pub fn store_account(&self, account_id: u32, account: &CachedAccount) -> io::Result<()> {
+        write_private_json(&self.account_dir(account_id).join("account.json"), account)
+    }
+
+    pub fn time_entries(
+        &self,
+        account_id: u32,
+        date: &chrono::NaiveDate,
+    ) -> Option<Vec<TimeEntry>> {
+        Cache::load(self.time_entries_path(account_id, date))
+    }
+
+    pub fn store_time_entries(
+        &self,
+        account_id: u32,
+        date: &chrono::NaiveDate,
+        time_entries: &[TimeEntry],
+    ) -> io::Result<()> {
+        write_private_json(&self.time_entries_path(account_id, date), &time_entries)?;
+        self.prune(account_id)
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn account_dir(&self, account_id: u32) -> PathBuf {
+        self.dir.join(account_id.to_string())
+    }
+
+    fn time_entries_path(&self, account_id: u32, date: &chrono::NaiveDate) -> PathBuf {
+        self.account_dir(account_id)
+            .join("time_entries")
+            .join(format!("{}.json", date))
+    }
+
+    /* a cache that can not be read is simply not used */
+    fn load<T: serde::de::DeserializeOwned>(path: PathBuf) -> Option<T> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn prune(&self, account_id: u32) -> io::Result<()> {
+        let dir = self.account_dir(account_id).join("time_entries");
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            files.push((entry.metadata()?.modified()?, entry.path()));
+        }
+
+        files.sort_by_key(|(modified, _path)| std::cmp::Reverse(*modified));
+        for (_modified, path) in files.iter().skip(Cache::KEEP_DAYS) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
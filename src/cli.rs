@@ -0,0 +1,490 @@
+use crate::{
+    credentials, duration_str_to_f32, f32_to_duration_str, fuzzy_matches, Harvest, HarvestError,
+    ProjectAssignment, TaskAssignment, TimeEntry,
+};
+use std::fmt;
+use std::io;
+
+/* the first argument that runs a command instead of starting the user interface */
+pub const COMMANDS: [&str; 7] = [
+    "start", "stop", "restart", "status", "list", "add", "delete",
+];
+
+pub const USAGE: &str = "Usage:
+  timer-for-harvest start <project> <task> [notes]
+  timer-for-harvest stop
+  timer-for-harvest restart <id>
+  timer-for-harvest status [--json | --format <template>]
+  timer-for-harvest list [--date YYYY-MM-DD] [--json | --format <template>]
+  timer-for-harvest add <project> <task> <duration> [notes]
+  timer-for-harvest delete <id>
+
+Projects and tasks are matched on part of their name, code or client.
+Templates can contain {id}, {date}, {project}, {code}, {client}, {task},
+{notes}, {hours}, {duration} and {running}.
+
+Status exits with 3 when no timer is running.";
+
+/* what --json prints, the same fields as the templates so scripts keep working when TimeEntry
+ * changes */
+#[derive(serde::Serialize)]
+struct JsonTimeEntry<'a> {
+    id: u32,
+    date: &'a str,
+    project: &'a str,
+    code: Option<&'a str>,
+    client: &'a str,
+    task: &'a str,
+    notes: Option<&'a str>,
+    hours: f32,
+    duration: String,
+    running: bool,
+}
+
+impl<'a> From<&'a TimeEntry> for JsonTimeEntry<'a> {
+    fn from(time_entry: &'a TimeEntry) -> JsonTimeEntry<'a> {
+        JsonTimeEntry {
+            id: time_entry.id,
+            date: &time_entry.spent_date,
+            project: &time_entry.project.name,
+            code: time_entry.project.code.as_deref(),
+            client: &time_entry.client.name,
+            task: &time_entry.task.name,
+            notes: time_entry.notes.as_deref(),
+            hours: time_entry.hours,
+            duration: f32_to_duration_str(time_entry.hours),
+            running: time_entry.is_running,
+        }
+    }
+}
+
+/* how status and list show time entries */
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    Text,
+    Json,
+    Format(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Start {
+        project: String,
+        task: String,
+        notes: String,
+    },
+    Stop,
+    Restart(u32),
+    Status(Output),
+    List(Option<chrono::NaiveDate>, Output),
+    Add {
+        project: String,
+        task: String,
+        hours: f32,
+        notes: String,
+    },
+    Delete(u32),
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    /* the arguments are not understood */
+    Usage(String),
+    /* no single project, task or time entry fits */
+    NoMatch(String),
+    /* status found no running timer */
+    NotRunning,
+    Harvest(HarvestError),
+    Output(io::Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::NoMatch(message) => write!(f, "{}", message),
+            CliError::NotRunning => write!(f, "No timer is running"),
+            CliError::Harvest(e) => write!(f, "{}", e),
+            CliError::Output(e) => write!(f, "Unable to write output: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<HarvestError> for CliError {
+    fn from(error: HarvestError) -> CliError {
+        CliError::Harvest(error)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(error: io::Error) -> CliError {
+        CliError::Output(error)
+    }
+}
+
+impl Command {
+    /* args start with the command name */
+    pub fn parse(args: &[String]) -> Result<Command, CliError> {
+        let name = args.first().map(|a| a.as_str()).unwrap_or("");
+        let args = if args.is_empty() { args } else { &args[1..] };
+        let usage = |message: &str| Err(CliError::Usage(message.to_string()));
+
+        match (name, args) {
+            ("start", [project, task]) => Ok(Command::Start {
+                project: project.clone(),
+                task: task.clone(),
+                notes: "".to_string(),
+            }),
+            ("start", [project, task, notes]) => Ok(Command::Start {
+                project: project.clone(),
+                task: task.clone(),
+                notes: notes.clone(),
+            }),
+            ("stop", []) => Ok(Command::Stop),
+            ("restart", [id]) => Ok(Command::Restart(Command::parse_id(id)?)),
+            ("status", options) => {
+                let (output, _date) = Command::parse_options(options, false)?;
+                Ok(Command::Status(output))
+            }
+            ("list", options) => {
+                let (output, date) = Command::parse_options(options, true)?;
+                Ok(Command::List(date, output))
+            }
+            ("add", [project, task, duration]) | ("add", [project, task, duration, _]) => {
+                let hours = duration_str_to_f32(duration);
+                /* Harvest would start a timer instead */
+                if hours <= 0.0 {
+                    return usage(&format!(
+                        "Invalid duration: {}, use hours:minutes",
+                        duration
+                    ));
+                }
+                Ok(Command::Add {
+                    project: project.clone(),
+                    task: task.clone(),
+                    hours: hours,
+                    notes: args.get(3).cloned().unwrap_or_default(),
+                })
+            }
+            ("delete", [id]) => Ok(Command::Delete(Command::parse_id(id)?)),
+            _ => usage(&format!("Invalid arguments for {}", name)),
+        }
+    }
+
+    fn parse_options(
+        options: &[String],
+        allow_date: bool,
+    ) -> Result<(Output, Option<chrono::NaiveDate>), CliError> {
+        let mut output = Output::Text;
+        let mut date = None;
+        let mut options = options.iter();
+
+        while let Some(option) = options.next() {
+            match option.as_str() {
+                "--json" if output == Output::Text => output = Output::Json,
+                "--format" if output == Output::Text => match options.next() {
+                    Some(template) => output = Output::Format(template.clone()),
+                    None => return Err(CliError::Usage("Missing template".to_string())),
+                },
+                "--date" if allow_date && date.is_none() => {
+                    let value = options.next().map(|v| v.as_str()).unwrap_or("");
+                    match chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                        Ok(value) => date = Some(value),
+                        Err(_) => return Err(CliError::Usage(format!("Invalid date: {}", value))),
+                    }
+                }
+                _ => return Err(CliError::Usage(format!("Invalid option: {}", option))),
+            }
+        }
+        Ok((output, date))
+    }
+
+    fn parse_id(id: &str) -> Result<u32, CliError> {
+        id.parse()
+            .map_err(|_| CliError::Usage(format!("Invalid time entry id: {}", id)))
+    }
+}
+
+pub struct Cli {
+    api: Harvest,
+    today: chrono::NaiveDate,
+}
+
+impl Cli {
+    pub fn new(api: Harvest, today: chrono::NaiveDate) -> Cli {
+        Cli {
+            api: api,
+            today: today,
+        }
+    }
+
+    /* runs the command given on the command line, returns the exit code */
+    pub fn main(args: &[String]) -> i32 {
+        let api = match Harvest::from_environment()
+            .or_else(|| Harvest::from_store(&*credentials::default_store()))
+        {
+            Some(api) => api,
+            None => {
+                eprintln!(
+                    "Not signed in to Harvest, start Timer for Harvest to sign in or set \
+                     TFH_ACCESS_TOKEN and TFH_ACCOUNT_ID"
+                );
+                return 1;
+            }
+        };
+        let cli = Cli::new(api, chrono::Local::today().naive_local());
+
+        match Command::parse(args).and_then(|command| cli.run(&command, &mut io::stdout())) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                match e {
+                    CliError::Usage(_) => 2,
+                    CliError::NotRunning => 3,
+                    _ => 1,
+                }
+            }
+        }
+    }
+
+    pub fn run(&self, command: &Command, out: &mut dyn io::Write) -> Result<(), CliError> {
+        match command {
+            Command::Start {
+                project,
+                task,
+                notes,
+            } => {
+                let (project_id, task_id) = self.find_project_and_task(project, task)?;
+                let time_entry =
+                    self.api
+                        .start_timer(project_id, task_id, notes.clone(), 0.0, &self.today)?;
+                writeln!(out, "Started {}", Cli::describe(&time_entry))?;
+            }
+            Command::Stop => {
+                let running = self.running_time_entries()?;
+                if running.is_empty() {
+                    return Err(CliError::NoMatch("No timer is running".to_string()));
+                }
+                for time_entry in running {
+                    let time_entry = self.api.stop_timer(time_entry.id)?;
+                    writeln!(out, "Stopped {}", Cli::describe(&time_entry))?;
+                }
+            }
+            Command::Restart(id) => {
+                let time_entry = self.api.restart_timer(*id)?;
+                writeln!(out, "Restarted {}", Cli::describe(&time_entry))?;
+            }
+            Command::Status(output) => {
+                let running = self.running_time_entries()?;
+                Cli::show(&running, output, out)?;
+                if running.is_empty() {
+                    return Err(CliError::NotRunning);
+                }
+            }
+            Command::List(date, output) => {
+                let date = date.unwrap_or(self.today).to_string();
+                let user = self.api.current_user()?;
+                let time_entries = self.api.time_entries_for(&user, date.clone(), date)?;
+                Cli::show(&time_entries, output, out)?;
+                if *output == Output::Text {
+                    let total_hours = time_entries.iter().fold(0.0, |total, te| total + te.hours);
+                    writeln!(out, "Total {}", f32_to_duration_str(total_hours))?;
+                }
+            }
+            Command::Add {
+                project,
+                task,
+                hours,
+                notes,
+            } => {
+                let (project_id, task_id) = self.find_project_and_task(project, task)?;
+                let time_entry = self.api.start_timer(
+                    project_id,
+                    task_id,
+                    notes.clone(),
+                    *hours,
+                    &self.today,
+                )?;
+                writeln!(out, "Added {}", Cli::describe(&time_entry))?;
+            }
+            Command::Delete(id) => {
+                let time_entry = self.api.delete_timer(*id)?;
+                writeln!(out, "Deleted {}", Cli::describe(&time_entry))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn running_time_entries(&self) -> Result<Vec<TimeEntry>, HarvestError> {
+        let user = self.api.current_user()?;
+        self.api.running_time_entries(&user)
+    }
+
+    fn find_project_and_task(&self, project: &str, task: &str) -> Result<(u32, u32), CliError> {
+        let project_assignments = self.api.active_project_assignments()?;
+        let project_assignment: &ProjectAssignment = Cli::find(
+            &project_assignments,
+            project,
+            "project",
+            |p| format!("{} ({})", p.project.name_and_code(), p.client.name),
+            |p| {
+                vec![
+                    p.project.name.clone(),
+                    p.project.code.clone().unwrap_or_default(),
+                ]
+            },
+        )?;
+        let task_assignment: &TaskAssignment = Cli::find(
+            &project_assignment.task_assignments,
+            task,
+            "task",
+            |t| t.task.name.clone(),
+            |t| vec![t.task.name.clone()],
+        )?;
+        Ok((project_assignment.project.id, task_assignment.task.id))
+    }
+
+    /* an exact match of one of the names wins, otherwise only one label may match */
+    fn find<'a, T, L, N>(
+        candidates: &'a [T],
+        key: &str,
+        kind: &str,
+        label: L,
+        names: N,
+    ) -> Result<&'a T, CliError>
+    where
+        L: Fn(&T) -> String,
+        N: Fn(&T) -> Vec<String>,
+    {
+        let exact: Vec<&T> = candidates
+            .iter()
+            .filter(|c| {
+                names(c)
+                    .iter()
+                    .any(|n| n.to_lowercase() == key.to_lowercase())
+            })
+            .collect();
+        if exact.len() == 1 {
+            return Ok(exact[0]);
+        }
+
+        let matching: Vec<&T> = candidates
+            .iter()
+            .filter(|c| fuzzy_matches(&label(c), key))
+            .collect();
+        match matching.as_slice() {
+            [candidate] => Ok(candidate),
+            [] => Err(CliError::NoMatch(format!(
+                "No {} matches \"{}\"",
+                kind, key
+            ))),
+            _ => Err(CliError::NoMatch(format!(
+                "More than one {} matches \"{}\": {}",
+                kind,
+                key,
+                matching
+                    .iter()
+                    .map(|c| label(c))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))),
+        }
+    }
+
+    fn show(
+        time_entries: &[TimeEntry],
+        output: &Output,
+        out: &mut dyn io::Write,
+    ) -> Result<(), CliError> {
+        match output {
+            Output::Text => {
+                for time_entry in time_entries {
+                    writeln!(out, "{}", Cli::describe(time_entry))?;
+                }
+            }
+            Output::Json => {
+                let time_entries: Vec<JsonTimeEntry> =
+                    time_entries.iter().map(JsonTimeEntry::from).collect();
+                serde_json::to_writer(&mut *out, &time_entries).map_err(io::Error::from)?;
+                writeln!(out)?;
+            }
+            Output::Format(template) => {
+                for time_entry in time_entries {
+                    writeln!(out, "{}", Cli::format(template, time_entry))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /* unknown placeholders are kept as is */
+    pub fn format(template: &str, time_entry: &TimeEntry) -> String {
+        let mut result = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+            let value = match &rest[1..end] {
+                "id" => time_entry.id.to_string(),
+                "date" => time_entry.spent_date.clone(),
+                "project" => time_entry.project.name.clone(),
+                "code" => time_entry.project.code.clone().unwrap_or_default(),
+                "client" => time_entry.client.name.clone(),
+                "task" => time_entry.task.name.clone(),
+                "notes" => Cli::notes(time_entry),
+                "hours" => format!("{:.2}", time_entry.hours),
+                "duration" => f32_to_duration_str(time_entry.hours),
+                "running" => time_entry.is_running.to_string(),
+                _ => {
+                    result.push('{');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            result.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+        result.push_str(rest);
+        result
+    }
+
+    /* one line per time entry, like in the main window */
+    fn notes(time_entry: &TimeEntry) -> String {
+        match &time_entry.notes {
+            Some(notes) => notes
+                .lines()
+                .filter(|l| !l.is_empty())
+                .collect::<Vec<&str>>()
+                .join(" - "),
+            None => "".to_string(),
+        }
+    }
+
+    fn describe(time_entry: &TimeEntry) -> String {
+        let notes = match Cli::notes(time_entry) {
+            notes if notes.is_empty() => notes,
+            notes => format!(" - {}", notes),
+        };
+        format!(
+            "{} {} {} ({}) - {}{}{}",
+            time_entry.id,
+            f32_to_duration_str(time_entry.hours),
+            time_entry.project.name_and_code(),
+            time_entry.client.name,
+            time_entry.task.name,
+            notes,
+            if time_entry.is_running {
+                " (running)"
+            } else {
+                ""
+            }
+        )
+    }
+}
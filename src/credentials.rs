@@ -0,0 +1,193 @@
+use crate::{Authorization, HarvestError};
+use dbus_secret_service::{EncryptionType, SecretService};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+
+/* somewhere to remember the authorization between application launches */
+pub trait CredentialStore {
+    fn load(&self) -> Result<Option<Authorization>, HarvestError>;
+    fn store(&self, authorization: &Authorization) -> Result<(), HarvestError>;
+    fn clear(&self) -> Result<(), HarvestError>;
+}
+
+/* the Secret Service (GNOME Keyring, KWallet etc) when it is running, a private file otherwise */
+pub fn default_store() -> Box<dyn CredentialStore> {
+    let file_store = FileStore::new(FileStore::default_path());
+
+    if SecretServiceStore::is_available() {
+        let secret_service_store = SecretServiceStore {};
+        /* earlier versions always used the file, move it over once. When this fails, keep
+         * the file around so the token is not lost */
+        match migrate(&file_store, &secret_service_store) {
+            Ok(()) => Box::new(secret_service_store),
+            Err(_) => Box::new(file_store),
+        }
+    } else {
+        Box::new(file_store)
+    }
+}
+
+/* forgets the authorization in every place it might have been stored */
+pub fn clear_all() -> Result<(), HarvestError> {
+    if SecretServiceStore::is_available() {
+        SecretServiceStore {}.clear()?;
+    }
+    FileStore::new(FileStore::default_path()).clear()
+}
+
+pub fn migrate(from: &dyn CredentialStore, to: &dyn CredentialStore) -> Result<(), HarvestError> {
+    if let Some(authorization) = from.load()? {
+        to.store(&authorization)?;
+        from.clear()?;
+    }
+    Ok(())
+}
+
+pub struct SecretServiceStore {}
+
+impl SecretServiceStore {
+    const LABEL: &'static str = "Timer for Harvest authorization";
+
+    pub fn is_available() -> bool {
+        SecretServiceStore::connect().is_ok()
+    }
+
+    fn connect() -> Result<SecretService, HarvestError> {
+        SecretService::connect(EncryptionType::Dh).map_err(SecretServiceStore::error)
+    }
+
+    fn attributes() -> HashMap<&'static str, &'static str> {
+        let mut attributes = HashMap::new();
+        attributes.insert("application", "nl.frankgroeneveld.timer-for-harvest");
+        attributes
+    }
+
+    fn error(error: dbus_secret_service::Error) -> HarvestError {
+        HarvestError::Credentials(format!("Secret Service: {}", error))
+    }
+}
+
+impl CredentialStore for SecretServiceStore {
+    fn load(&self) -> Result<Option<Authorization>, HarvestError> {
+        let service = SecretServiceStore::connect()?;
+        let items = service
+            .search_items(SecretServiceStore::attributes())
+            .map_err(SecretServiceStore::error)?;
+
+        let item = match items.unlocked.first().or(items.locked.first()) {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        /* might show a prompt to unlock the keyring */
+        item.ensure_unlocked().map_err(SecretServiceStore::error)?;
+        let secret = item.get_secret().map_err(SecretServiceStore::error)?;
+
+        serde_json::from_slice(&secret).map(Some).map_err(|e| {
+            HarvestError::Credentials(format!(
+                "Invalid stored authorization at line {} column {}",
+                e.line(),
+                e.column()
+            ))
+        })
+    }
+
+    fn store(&self, authorization: &Authorization) -> Result<(), HarvestError> {
+        let service = SecretServiceStore::connect()?;
+        let collection = service
+            .get_default_collection()
+            .map_err(SecretServiceStore::error)?;
+        collection
+            .ensure_unlocked()
+            .map_err(SecretServiceStore::error)?;
+
+        let secret = serde_json::to_vec(authorization).unwrap();
+        collection
+            .create_item(
+                SecretServiceStore::LABEL,
+                SecretServiceStore::attributes(),
+                &secret,
+                true,
+                "text/plain",
+            )
+            .map_err(SecretServiceStore::error)?;
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), HarvestError> {
+        let service = SecretServiceStore::connect()?;
+        let items = service
+            .search_items(SecretServiceStore::attributes())
+            .map_err(SecretServiceStore::error)?;
+
+        for item in items.unlocked.iter().chain(items.locked.iter()) {
+            item.delete().map_err(SecretServiceStore::error)?;
+        }
+        Ok(())
+    }
+}
+
+/* a json file only readable by the current user */
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    const CONFIG_FILE_NAME: &'static str = "timer-for-harvest.json";
+
+    pub fn new(path: PathBuf) -> FileStore {
+        FileStore { path: path }
+    }
+
+    pub fn default_path() -> PathBuf {
+        let mut path = dirs::config_dir().expect("Unable to find XDG config dir path");
+        path.push(FileStore::CONFIG_FILE_NAME);
+        path
+    }
+}
+
+impl CredentialStore for FileStore {
+    fn load(&self) -> Result<Option<Authorization>, HarvestError> {
+        match fs::read_to_string(&self.path) {
+            /* the error could quote the token, so only where it went wrong is shown */
+            Ok(content) => serde_json::from_str(&content).map(Some).map_err(|e| {
+                HarvestError::Credentials(format!(
+                    "Invalid configuration file {} at line {} column {}",
+                    self.path.display(),
+                    e.line(),
+                    e.column()
+                ))
+            }),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn store(&self, authorization: &Authorization) -> Result<(), HarvestError> {
+        let error = |e: std::io::Error| {
+            HarvestError::Credentials(format!("Unable to save {}: {}", self.path.display(), e))
+        };
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)
+            .map_err(error)?;
+        /* mode only applies to new files, files written by earlier versions were world readable */
+        fs::set_permissions(&self.path, fs::Permissions::from_mode(0o600)).map_err(error)?;
+        file.write_all(serde_json::to_string(authorization).unwrap().as_bytes())
+            .map_err(error)
+    }
+
+    fn clear(&self) -> Result<(), HarvestError> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(HarvestError::Credentials(
+                format!("Unable to remove {}: {}", self.path.display(), e),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::TimeEntry;
+use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+use dbus::blocking::Connection;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/* where the time since the last keyboard or mouse input comes from */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /* GNOME */
+    Mutter,
+    /* KDE and most other desktops */
+    ScreenSaver,
+    /* any session managed by systemd, only as precise as the desktop sets it */
+    Logind,
+}
+
+pub struct IdleMonitor {
+    connection: Connection,
+    backend: Backend,
+}
+
+/* a period without input, from the last input until input was seen again */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Away {
+    pub since: SystemTime,
+    pub duration: Duration,
+}
+
+/* turns idle times, polled now and then, into periods away */
+pub struct IdleWatch {
+    threshold: Duration,
+    idle_since: Option<SystemTime>,
+}
+
+impl IdleMonitor {
+    pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /* on the session and system bus, or both on the bus at the given address */
+    pub fn connect(address: Option<String>) -> Result<IdleMonitor, dbus::Error> {
+        let session = match &address {
+            Some(address) => Connection::new_address(address)?,
+            None => Connection::new_session()?,
+        };
+        for backend in &[Backend::Mutter, Backend::ScreenSaver] {
+            if IdleMonitor::query(&session, *backend).is_ok() {
+                return Ok(IdleMonitor {
+                    connection: session,
+                    backend: *backend,
+                });
+            }
+        }
+
+        let system = match &address {
+            Some(_) => session,
+            None => Connection::new_system()?,
+        };
+        IdleMonitor::query(&system, Backend::Logind)?;
+        Ok(IdleMonitor {
+            connection: system,
+            backend: Backend::Logind,
+        })
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /* the time since the last input */
+    pub fn idle_time(&self) -> Result<Duration, dbus::Error> {
+        IdleMonitor::query(&self.connection, self.backend)
+    }
+
+    /* calls on_return each time input is seen after at least threshold without */
+    pub fn watch<F>(self, threshold: Duration, interval: Duration, on_return: F)
+    where
+        F: Fn(Away) + Send + 'static,
+    {
+        thread::spawn(move || {
+            let mut watch = IdleWatch::new(threshold);
+            loop {
+                /* the desktop might restart, keep trying */
+                if let Ok(idle) = self.idle_time() {
+                    if let Some(away) = watch.update(idle, SystemTime::now()) {
+                        on_return(away);
+                    }
+                }
+                thread::sleep(interval);
+            }
+        });
+    }
+
+    fn query(connection: &Connection, backend: Backend) -> Result<Duration, dbus::Error> {
+        match backend {
+            Backend::Mutter => {
+                let proxy = connection.with_proxy(
+                    "org.gnome.Mutter.IdleMonitor",
+                    "/org/gnome/Mutter/IdleMonitor/Core",
+                    IdleMonitor::TIMEOUT,
+                );
+                let (milliseconds,): (u64,) =
+                    proxy.method_call("org.gnome.Mutter.IdleMonitor", "GetIdletime", ())?;
+                Ok(Duration::from_millis(milliseconds))
+            }
+            Backend::ScreenSaver => {
+                let proxy = connection.with_proxy(
+                    "org.freedesktop.ScreenSaver",
+                    "/org/freedesktop/ScreenSaver",
+                    IdleMonitor::TIMEOUT,
+                );
+                let (seconds,): (u32,) =
+                    proxy.method_call("org.freedesktop.ScreenSaver", "GetSessionIdleTime", ())?;
+                Ok(Duration::from_secs(seconds.into()))
+            }
+            Backend::Logind => {
+                let proxy = connection.with_proxy(
+                    "org.freedesktop.login1",
+                    "/org/freedesktop/login1/session/auto",
+                    IdleMonitor::TIMEOUT,
+                );
+                let idle: bool = proxy.get("org.freedesktop.login1.Session", "IdleHint")?;
+                if !idle {
+                    return Ok(Duration::from_secs(0));
+                }
+                let since: u64 = proxy.get("org.freedesktop.login1.Session", "IdleSinceHint")?;
+                let since = UNIX_EPOCH + Duration::from_micros(since);
+                Ok(SystemTime::now().duration_since(since).unwrap_or_default())
+            }
+        }
+    }
+}
+
+impl Away {
+    /* the hours of the time entry that fall in the time away, counted up to now since its
+     * hours might not have been updated while asleep */
+    pub fn hours_of(&self, time_entry: &TimeEntry, now: chrono::DateTime<chrono::Utc>) -> f32 {
+        (self.duration.as_secs() as f32 / 3600.0).min(time_entry.hours_at(now))
+    }
+}
+
+impl IdleWatch {
+    pub fn new(threshold: Duration) -> IdleWatch {
+        IdleWatch {
+            threshold: threshold,
+            idle_since: None,
+        }
+    }
+
+    /* idle is the time since the last input at now */
+    pub fn update(&mut self, idle: Duration, now: SystemTime) -> Option<Away> {
+        let last_input = now - idle;
+        if idle >= self.threshold {
+            if self.idle_since.is_none() {
+                self.idle_since = Some(last_input);
+            }
+            return None;
+        }
+
+        let since = self.idle_since.take()?;
+        Some(Away {
+            since: since,
+            duration: last_input.duration_since(since).unwrap_or_default(),
+        })
+    }
+}
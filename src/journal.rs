@@ -0,0 +1,475 @@
+use crate::{write_private_json, Harvest, HarvestError, TimeEntry};
+use chrono::TimeZone;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/* what was done to a time entry while it might not be possible to reach Harvest */
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub enum Change {
+    /* a new time entry, with a local id until Harvest saved it */
+    Start(TimeEntry),
+    Restart(u32),
+    Stop(u32),
+    /* the time entry with the chosen project, task, notes and hours */
+    Update(TimeEntry),
+}
+
+impl Change {
+    pub fn time_entry_id(&self) -> u32 {
+        match self {
+            Change::Start(time_entry) | Change::Update(time_entry) => time_entry.id,
+            Change::Restart(id) | Change::Stop(id) => *id,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct PendingChange {
+    pub account_id: u32,
+    pub change: Change,
+    /* the time entry as it was shown, only kept for the first change to it to detect conflicts */
+    pub base: Option<TimeEntry>,
+    /* unix time, for a new time entry the time its timer (re)started */
+    pub made_at: u64,
+}
+
+impl PendingChange {
+    /* the best known version of the time entry, if any */
+    pub fn time_entry(&self) -> Option<&TimeEntry> {
+        match &self.change {
+            Change::Start(time_entry) | Change::Update(time_entry) => Some(time_entry),
+            Change::Restart(_) | Change::Stop(_) => self.base.as_ref(),
+        }
+    }
+}
+
+pub enum Problem {
+    /* changed or, when None, deleted by someone else before the change was saved */
+    Conflict(PendingChange, Option<Box<TimeEntry>>),
+    /* Harvest did not accept the change */
+    Rejected(PendingChange, HarvestError),
+}
+
+/* failing to replay a change, only interrupted ones are tried again */
+enum Replay {
+    Conflict(Option<Box<TimeEntry>>),
+    Rejected(HarvestError),
+    Interrupted(HarvestError),
+}
+
+impl From<HarvestError> for Replay {
+    fn from(error: HarvestError) -> Replay {
+        match error {
+            HarvestError::Transport(_) | HarvestError::AuthExpired => Replay::Interrupted(error),
+            /* Harvest is down or busy, it gave up retrying but the change is still fine */
+            HarvestError::Status(status, _) if status == 429 || status >= 500 => {
+                Replay::Interrupted(error)
+            }
+            error => Replay::Rejected(error),
+        }
+    }
+}
+
+/* applies the changes made here since base to theirs, changes made in Harvest to anything else
+ * are kept */
+pub fn merge(base: &TimeEntry, mine: &TimeEntry, theirs: &TimeEntry) -> TimeEntry {
+    let mut merged = theirs.clone();
+
+    /* tasks belong to a project, so they are taken together */
+    if mine.project.id != base.project.id || mine.task.id != base.task.id {
+        merged.project = mine.project.clone();
+        merged.client = mine.client.clone();
+        merged.task = mine.task.clone();
+    }
+    /* no notes and empty notes are the same to Harvest */
+    if mine.notes.as_deref().unwrap_or("") != base.notes.as_deref().unwrap_or("") {
+        merged.notes = mine.notes.clone();
+    }
+    if !base.is_running && (mine.hours - base.hours).abs() > 0.005 {
+        merged.hours = mine.hours;
+    }
+    merged
+}
+
+/* changes to time entries in the order they were made, kept on disk until saved in Harvest */
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Journal {
+    #[serde(skip)]
+    path: PathBuf,
+    /* counts down, far away from the ids Harvest hands out */
+    next_local_id: u32,
+    changes: Vec<PendingChange>,
+}
+
+impl Journal {
+    pub fn new(path: PathBuf) -> Journal {
+        Journal {
+            path: path,
+            next_local_id: u32::MAX,
+            changes: vec![],
+        }
+    }
+
+    pub fn default_path() -> PathBuf {
+        let mut path = dirs::data_dir().expect("Unable to find XDG data dir path");
+        path.push("timer-for-harvest");
+        path.push("pending_changes.json");
+        path
+    }
+
+    pub fn load(path: PathBuf) -> Result<Journal, HarvestError> {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Journal::new(path)),
+            Err(e) => return Err(Journal::error(&path, e)),
+        };
+
+        let mut journal: Journal = serde_json::from_str(&content).map_err(|e| {
+            HarvestError::Journal(format!("Invalid file {}: {}", path.display(), e))
+        })?;
+        journal.path = path;
+        Ok(journal)
+    }
+
+    pub fn has_changes(&self, account_id: u32) -> bool {
+        self.changes.iter().any(|c| c.account_id == account_id)
+    }
+
+    /* the time entries with changes that are not saved in Harvest yet */
+    pub fn pending(&self, account_id: u32) -> Vec<u32> {
+        let mut ids: Vec<u32> = self
+            .changes
+            .iter()
+            .filter(|c| c.account_id == account_id)
+            .map(|c| c.change.time_entry_id())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    /* whether the time entry only exists here */
+    pub fn is_local(&self, account_id: u32, id: u32) -> bool {
+        self.local_start(account_id, id).is_some()
+    }
+
+    /* shown is the time entry as currently shown, not needed for new ones */
+    pub fn record(
+        &mut self,
+        account_id: u32,
+        change: Change,
+        shown: Option<&TimeEntry>,
+    ) -> Result<(), HarvestError> {
+        let now = Journal::now();
+        let change = match change {
+            Change::Start(mut time_entry) => {
+                time_entry.id = self.next_local_id;
+                self.next_local_id -= 1;
+                Change::Start(time_entry)
+            }
+            change => change,
+        };
+
+        if !self.fold(account_id, &change, now) {
+            let id = change.time_entry_id();
+            let first = !self
+                .changes
+                .iter()
+                .any(|c| c.account_id == account_id && c.change.time_entry_id() == id);
+            self.changes.push(PendingChange {
+                account_id: account_id,
+                change: change,
+                base: if first { shown.cloned() } else { None },
+                made_at: now,
+            });
+        }
+        self.save()
+    }
+
+    /* drops all changes to a time entry, used after deleting it */
+    pub fn forget(&mut self, account_id: u32, id: u32) -> Result<(), HarvestError> {
+        self.changes
+            .retain(|c| c.account_id != account_id || c.change.time_entry_id() != id);
+        self.save()
+    }
+
+    pub fn clear(&mut self) -> Result<(), HarvestError> {
+        self.changes.clear();
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Journal::error(&self.path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    /* shows the changes in time entries of spent_date retrieved from Harvest or the cache */
+    pub fn apply(&self, account_id: u32, spent_date: &str, time_entries: &mut Vec<TimeEntry>) {
+        let now = Journal::datetime(Journal::now());
+
+        for pending in self.changes.iter().filter(|c| c.account_id == account_id) {
+            let made_at = Journal::datetime(pending.made_at);
+            match &pending.change {
+                Change::Start(time_entry) => {
+                    let mut time_entry = time_entry.clone();
+                    if time_entry.is_running {
+                        /* like Harvest does when starting a timer */
+                        for t in time_entries.iter_mut().filter(|t| t.is_running) {
+                            t.stop_timer_at(made_at);
+                        }
+                        time_entry.start_timer_at(made_at);
+                        time_entry.hours = time_entry.hours_at(now);
+                    }
+                    if time_entry.spent_date == spent_date {
+                        time_entries.push(time_entry);
+                    }
+                }
+                Change::Restart(id) => {
+                    for t in time_entries.iter_mut() {
+                        if t.id == *id && !t.is_running {
+                            t.start_timer_at(made_at);
+                            t.hours = t.hours_at(now);
+                        } else if t.id != *id && t.is_running {
+                            t.stop_timer_at(made_at);
+                        }
+                    }
+                }
+                Change::Stop(id) => {
+                    for t in time_entries
+                        .iter_mut()
+                        .filter(|t| t.id == *id && t.is_running)
+                    {
+                        t.stop_timer_at(made_at);
+                    }
+                }
+                Change::Update(edited) => {
+                    for t in time_entries.iter_mut().filter(|t| t.id == edited.id) {
+                        Journal::edit(t, edited);
+                    }
+                }
+            }
+        }
+    }
+
+    /* saves the changes to the current account of api in the order they were made. Stops at a
+     * change that could not be sent, it is retried on the next call. The reason is returned then,
+     * unless there are problems to report first */
+    pub fn sync(&mut self, api: &Harvest) -> Result<Vec<Problem>, HarvestError> {
+        let mut problems = vec![];
+
+        while let Some(index) = self
+            .changes
+            .iter()
+            .position(|c| c.account_id == api.account_id())
+        {
+            let pending = self.changes[index].clone();
+            match Journal::replay(api, &pending) {
+                Ok(()) => {
+                    self.changes.remove(index);
+                }
+                Err(Replay::Conflict(current)) => {
+                    /* the later changes were based on this one */
+                    let id = pending.change.time_entry_id();
+                    self.changes.retain(|c| {
+                        c.account_id != api.account_id() || c.change.time_entry_id() != id
+                    });
+                    problems.push(Problem::Conflict(pending, current));
+                }
+                Err(Replay::Rejected(e)) => {
+                    self.changes.remove(index);
+                    problems.push(Problem::Rejected(pending, e));
+                }
+                Err(Replay::Interrupted(e)) => {
+                    self.save()?;
+                    return if problems.is_empty() {
+                        Err(e)
+                    } else {
+                        Ok(problems)
+                    };
+                }
+            }
+            /* never send a change twice */
+            self.save()?;
+        }
+
+        Ok(problems)
+    }
+
+    fn replay(api: &Harvest, pending: &PendingChange) -> Result<(), Replay> {
+        /* a timer that ran or stopped while offline should not count the time until now */
+        let late = Journal::hours_between(pending.made_at, Journal::now());
+
+        if let Change::Start(time_entry) = &pending.change {
+            let hours = if time_entry.is_running && late >= 1.0 / 60.0 {
+                time_entry.hours + late
+            } else {
+                time_entry.hours
+            };
+            let spent_date = time_entry
+                .spent_date
+                .parse()
+                .map_err(|_| Replay::Rejected(HarvestError::Journal("Invalid date".to_string())))?;
+            let created = match api.start_timer(
+                time_entry.project.id,
+                time_entry.task.id,
+                time_entry.notes.clone().unwrap_or_default(),
+                hours,
+                &spent_date,
+            ) {
+                Ok(created) => created,
+                /* Harvest might have created it before the connection broke, sending it again
+                 * could start a second timer */
+                Err(HarvestError::Transport(e)) if !e.is_connect() => {
+                    return Err(Replay::Rejected(HarvestError::Transport(e)))
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            /* Harvest only starts timers without hours, not sent again once created */
+            let result = if time_entry.is_running && !created.is_running {
+                api.restart_timer(created.id)
+            } else if !time_entry.is_running && created.is_running {
+                api.stop_timer(created.id)
+            } else {
+                Ok(created)
+            };
+            return result.map(|_| ()).map_err(Replay::Rejected);
+        }
+
+        let id = pending.change.time_entry_id();
+        let current = match api.time_entry(id) {
+            Err(HarvestError::Status(404, _)) => return Err(Replay::Conflict(None)),
+            result => result?,
+        };
+        match (&pending.change, &pending.base) {
+            /* only edits could undo what was changed in Harvest */
+            (Change::Update(_), Some(base)) if current.changed_since(base) => {
+                return Err(Replay::Conflict(Some(Box::new(current))));
+            }
+            /* already done in Harvest, for example using the website */
+            (Change::Stop(_), _) if !current.is_running => return Ok(()),
+            (Change::Restart(_), _) if current.is_running => return Ok(()),
+            _ => {}
+        }
+
+        match &pending.change {
+            Change::Restart(_) => {
+                /* Harvest continues with the hours it has, so add the time offline first */
+                if late >= 1.0 / 60.0 {
+                    api.update_timer(
+                        id,
+                        current.project.id,
+                        current.task.id,
+                        current.notes.clone().unwrap_or_default(),
+                        current.hours + late,
+                        false,
+                        current.spent_date.clone(),
+                    )?;
+                    api.restart_timer(id).map_err(Replay::Rejected)?;
+                } else {
+                    api.restart_timer(id)?;
+                }
+            }
+            Change::Stop(_) => {
+                let stopped = api.stop_timer(id)?;
+                if late >= 1.0 / 60.0 && stopped.hours > late {
+                    api.update_timer(
+                        id,
+                        stopped.project.id,
+                        stopped.task.id,
+                        stopped.notes.unwrap_or_default(),
+                        stopped.hours - late,
+                        false,
+                        stopped.spent_date,
+                    )
+                    .map_err(Replay::Rejected)?;
+                }
+            }
+            Change::Update(edited) => {
+                api.update_timer(
+                    id,
+                    edited.project.id,
+                    edited.task.id,
+                    edited.notes.clone().unwrap_or_default(),
+                    edited.hours,
+                    current.is_running,
+                    current.spent_date,
+                )?;
+            }
+            Change::Start(_) => {}
+        }
+        Ok(())
+    }
+
+    /* changes to a time entry that Harvest does not have yet are made to the one to create */
+    fn fold(&mut self, account_id: u32, change: &Change, now: u64) -> bool {
+        let index = match self.local_start(account_id, change.time_entry_id()) {
+            Some(index) => index,
+            None => return false,
+        };
+        let pending = &mut self.changes[index];
+        let time_entry = match &mut pending.change {
+            Change::Start(time_entry) => time_entry,
+            _ => return false,
+        };
+
+        match change {
+            Change::Start(_) => return false,
+            Change::Restart(_) => {
+                if !time_entry.is_running {
+                    time_entry.is_running = true;
+                    pending.made_at = now;
+                }
+            }
+            Change::Stop(_) => {
+                if time_entry.is_running {
+                    time_entry.is_running = false;
+                    time_entry.hours += Journal::hours_between(pending.made_at, now);
+                }
+            }
+            Change::Update(edited) => Journal::edit(time_entry, edited),
+        }
+        true
+    }
+
+    fn local_start(&self, account_id: u32, id: u32) -> Option<usize> {
+        self.changes.iter().position(|c| match &c.change {
+            Change::Start(time_entry) => c.account_id == account_id && time_entry.id == id,
+            _ => false,
+        })
+    }
+
+    /* the hours of a running timer are counted by Harvest */
+    fn edit(time_entry: &mut TimeEntry, edited: &TimeEntry) {
+        time_entry.project = edited.project.clone();
+        time_entry.client = edited.client.clone();
+        time_entry.task = edited.task.clone();
+        time_entry.notes = edited.notes.clone();
+        if !time_entry.is_running {
+            time_entry.hours = edited.hours;
+        }
+    }
+
+    fn save(&self) -> Result<(), HarvestError> {
+        write_private_json(&self.path, self).map_err(|e| Journal::error(&self.path, e))
+    }
+
+    fn error(path: &std::path::Path, error: io::Error) -> HarvestError {
+        HarvestError::Journal(format!("{}: {}", path.display(), error))
+    }
+
+    fn hours_between(from: u64, till: u64) -> f32 {
+        till.saturating_sub(from) as f32 / 3600.0
+    }
+
+    fn datetime(seconds: u64) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.timestamp(seconds as i64, 0)
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
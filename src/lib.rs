@@ -1,22 +1,104 @@
-use dirs;
+pub mod cache;
+pub mod cli;
+pub mod credentials;
+pub mod idle;
+pub mod journal;
+pub mod login;
+pub mod service;
+pub mod session;
+pub mod week;
+
+use credentials::CredentialStore;
 use hyper;
 use serde;
-use serde_json::json;
-use std::fs::write;
-use std::fs::File;
-use std::io::Read;
-use std::io::Write;
-use std::net::TcpListener;
-use std::net::TcpStream;
-use std::path::PathBuf;
-use std::process::Command;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Harvest {
-    token: String,
-    account_id: u32,
-    expires_at: u64,
+    authorization: Authorization,
+    api_url: String,
+    id_url: String,
+    client: reqwest::blocking::Client,
+    retry_policy: RetryPolicy,
+}
+
+/* how often and how patiently failed requests are tried again */
+#[derive(Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    /* longer waits, including a longer Retry-After from Harvest, are not worth blocking for */
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/* the part of Harvest that is remembered between application launches */
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Debug)]
+pub struct Authorization {
+    pub token: String,
+    /* the account currently in use */
+    pub account_id: u32,
+    pub expires_at: u64,
+    /* all accounts the token grants access to, missing in earlier versions */
+    #[serde(default)]
+    pub account_ids: Vec<u32>,
+}
+
+impl Authorization {
+    /* created by hand at id.getharvest.com/developers, these do not expire */
+    pub fn personal_access_token(token: String, account_id: u32) -> Authorization {
+        Authorization {
+            token: token,
+            account_id: account_id,
+            expires_at: u64::MAX,
+            account_ids: vec![account_id],
+        }
+    }
+
+    /* whole days left, None for tokens that do not expire */
+    pub fn expires_in_days(&self) -> Option<u64> {
+        if self.expires_at == u64::MAX {
+            return None;
+        }
+
+        let unix_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        Some(self.expires_at.saturating_sub(unix_timestamp) / (60 * 60 * 24))
+    }
+
+    pub fn account_ids(&self) -> Vec<u32> {
+        if self.account_ids.is_empty() {
+            vec![self.account_id]
+        } else {
+            self.account_ids.clone()
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct Account {
+    pub id: u32,
+    pub name: String,
+    pub product: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct AccountsResponse {
+    pub accounts: Vec<Account>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
@@ -64,6 +146,14 @@ pub struct TimeEntry {
     pub task: Task,
     pub notes: Option<String>,
     pub is_running: bool,
+    /* missing in time entries cached by earlier versions and in new ones not saved yet */
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    /* when a running timer was last started, and the hours it had then */
+    #[serde(default)]
+    pub timer_started_at: Option<String>,
+    #[serde(default)]
+    pub hours_without_timer: Option<f32>,
 }
 
 /* a partially filled TimeEntry with id's instead of objects (Project etc) */
@@ -89,6 +179,19 @@ pub struct TimerWithoutHours {
     pub is_running: bool,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct PageLinks {
+    pub next: Option<String>,
+}
+
+/* shared behaviour of the paginated list responses, see Harvest::api_get_all_pages */
+pub trait Page: serde::de::DeserializeOwned {
+    type Record;
+
+    fn next_page(&self) -> Option<String>;
+    fn into_records(self) -> Vec<Self::Record>;
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct ProjectPage {
     pub projects: Vec<Project>,
@@ -96,6 +199,8 @@ pub struct ProjectPage {
     pub total_pages: u32,
     pub total_entries: u32,
     pub page: u32,
+    #[serde(default)]
+    pub links: PageLinks,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -105,6 +210,8 @@ pub struct ProjectAssignmentPage {
     pub total_pages: u32,
     pub total_entries: u32,
     pub page: u32,
+    #[serde(default)]
+    pub links: PageLinks,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -114,6 +221,8 @@ pub struct TimeEntryPage {
     pub total_pages: u32,
     pub total_entries: u32,
     pub page: u32,
+    #[serde(default)]
+    pub links: PageLinks,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -123,13 +232,166 @@ pub struct TaskAssignmentPage {
     pub total_pages: u32,
     pub total_entries: u32,
     pub page: u32,
+    #[serde(default)]
+    pub links: PageLinks,
 }
 
+macro_rules! impl_page {
+    ($page:ident, $records:ident, $record:ident) => {
+        impl Page for $page {
+            type Record = $record;
+
+            fn next_page(&self) -> Option<String> {
+                self.links.next.clone()
+            }
+
+            fn into_records(self) -> Vec<$record> {
+                self.$records
+            }
+        }
+    };
+}
+
+impl_page!(ProjectPage, projects, Project);
+impl_page!(
+    ProjectAssignmentPage,
+    project_assignments,
+    ProjectAssignment
+);
+impl_page!(TimeEntryPage, time_entries, TimeEntry);
+impl_page!(TaskAssignmentPage, task_assignments, TaskAssignment);
+
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct User {
     pub id: u32,
 }
 
+/* error body as returned by Harvest, either from the API or the OAuth layer */
+#[derive(serde::Deserialize)]
+struct ErrorBody {
+    message: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum HarvestError {
+    /* unable to talk to Harvest at all: DNS, TLS, connection or timeout problems */
+    Transport(reqwest::Error),
+    /* Harvest answered with an unsuccessful status code and message */
+    Status(u16, String),
+    /* the response body did not have the expected structure */
+    Deserialize(serde_json::Error, String),
+    /* the authorization token was rejected, a new one needs to be obtained */
+    AuthExpired,
+    /* the authorization could not be read from or written to the credential store */
+    Credentials(String),
+    /* signing in through the browser did not result in an authorization */
+    Login(String),
+    /* the user stopped signing in */
+    LoginCancelled,
+    /* the changes waiting to be saved in Harvest could not be read or written */
+    Journal(String),
+}
+
+impl fmt::Display for HarvestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HarvestError::Transport(e) => write!(f, "Unable to reach Harvest: {}", e),
+            HarvestError::Status(status, message) => {
+                write!(f, "Harvest returned an error ({}): {}", status, message)
+            }
+            HarvestError::Deserialize(e, body) => {
+                write!(f, "Unexpected response from Harvest ({}): {}", e, body)
+            }
+            HarvestError::AuthExpired => {
+                write!(f, "Harvest authorization has expired, please sign in again")
+            }
+            HarvestError::Credentials(message) => {
+                write!(f, "Unable to access stored authorization: {}", message)
+            }
+            HarvestError::Login(message) => write!(f, "Unable to sign in to Harvest: {}", message),
+            HarvestError::LoginCancelled => write!(f, "Signing in to Harvest was cancelled"),
+            HarvestError::Journal(message) => {
+                write!(
+                    f,
+                    "Unable to keep changes not saved in Harvest yet: {}",
+                    message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for HarvestError {}
+
+impl From<reqwest::Error> for HarvestError {
+    fn from(error: reqwest::Error) -> HarvestError {
+        HarvestError::Transport(error)
+    }
+}
+
+impl TimeEntry {
+    /* whether it was changed by someone else since earlier was retrieved */
+    pub fn changed_since(&self, earlier: &TimeEntry) -> bool {
+        /* the timestamps Harvest uses can be compared as text */
+        if let (Some(updated_at), Some(earlier_updated_at)) =
+            (&self.updated_at, &earlier.updated_at)
+        {
+            return updated_at > earlier_updated_at;
+        }
+
+        /* the hours of a running timer always change */
+        self.project.id != earlier.project.id
+            || self.task.id != earlier.task.id
+            || self.notes != earlier.notes
+            || self.spent_date != earlier.spent_date
+            || self.is_running != earlier.is_running
+            || (!self.is_running && (self.hours - earlier.hours).abs() > 0.005)
+    }
+
+    /* the hours of a running timer keep going up from when it was started, not only when
+     * retrieved again */
+    pub fn hours_at(&self, now: chrono::DateTime<chrono::Utc>) -> f32 {
+        if !self.is_running {
+            return self.hours;
+        }
+        let started = self
+            .timer_started_at
+            .as_ref()
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok());
+        match (started, self.hours_without_timer) {
+            (Some(started), Some(hours)) => {
+                let elapsed = now.signed_duration_since(started).num_milliseconds().max(0);
+                hours + elapsed as f32 / 3_600_000.0
+            }
+            _ => self.hours,
+        }
+    }
+
+    /* running from now on, with the hours it has */
+    pub fn start_timer_at(&mut self, now: chrono::DateTime<chrono::Utc>) {
+        self.is_running = true;
+        self.timer_started_at = Some(now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+        self.hours_without_timer = Some(self.hours);
+    }
+
+    /* stopped at now with hours taken off, like time nobody was working */
+    pub fn stopped_without(&self, hours: f32, now: chrono::DateTime<chrono::Utc>) -> TimeEntry {
+        let mut stopped = self.clone();
+        stopped.stop_timer_at(now);
+        stopped.hours = (stopped.hours - hours).max(0.0);
+        stopped
+    }
+
+    /* keeps the hours it had at now */
+    pub fn stop_timer_at(&mut self, now: chrono::DateTime<chrono::Utc>) {
+        self.hours = self.hours_at(now);
+        self.is_running = false;
+        self.timer_started_at = None;
+        self.hours_without_timer = None;
+    }
+}
+
 impl Project {
     pub fn name_and_code(&self) -> String {
         if self.code == None || self.code.as_ref().unwrap() == "" {
@@ -141,120 +403,122 @@ impl Project {
 }
 
 impl Harvest {
-    const CLIENT_ID: &'static str = "ew1-8t73wKHsqmhRNtxwkBaO";
-    const CONFIG_FILE_NAME: &'static str = "timer-for-harvest.json";
-
-    pub fn new() -> Harvest {
-        match Harvest::read_authorization_from_file() {
-            Some(harvest) => {
-                let unix_timestamp = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                let one_day = 60 * 60 * 24;
-
-                if harvest.expires_at < unix_timestamp + one_day {
-                    Harvest::obtain_new_authorization()
-                } else {
-                    return harvest;
-                }
-            }
-            None => Harvest::obtain_new_authorization(),
+    pub(crate) const CLIENT_ID: &'static str = "ew1-8t73wKHsqmhRNtxwkBaO";
+    const API_URL: &'static str = "https://api.harvestapp.com/v2";
+    const ID_URL: &'static str = "https://id.getharvest.com";
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /* None when there is no usable authorization yet, the user needs to sign in first */
+    pub fn from_store(store: &dyn CredentialStore) -> Option<Harvest> {
+        /* an unreadable authorization is treated like a missing one, by signing in again */
+        let authorization = store.load().unwrap_or(None)?;
+        let unix_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let one_day = 60 * 60 * 24;
+
+        /* personal access tokens never get here, they expire at u64::MAX */
+        if authorization.expires_at < unix_timestamp + one_day {
+            None
+        } else {
+            Some(Harvest::from_authorization(authorization))
         }
     }
 
-    fn obtain_new_authorization() -> Harvest {
-        let listener = TcpListener::bind("127.0.0.1:12345").expect("Port 12345 is already in use");
-
-        Command::new("xdg-open")
-            .arg(format!(
-                "https://id.getharvest.com/oauth2/authorize?client_id={}&response_type=token",
-                Harvest::CLIENT_ID
-            ))
-            .spawn()
-            .expect("Unable to open browser");
-
-        for stream in listener.incoming() {
-            let stream = stream.unwrap();
-            let result = Harvest::authorize_callback(stream);
+    pub fn from_authorization(authorization: Authorization) -> Harvest {
+        let client = Harvest::http_client(&authorization);
 
-            let unix_timestamp = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-            let expires_in: u64 = result.2.parse().unwrap();
-
-            let harvest = Harvest {
-                token: result.0,
-                account_id: result.1.parse().unwrap(),
-                expires_at: unix_timestamp + expires_in,
-            };
-            harvest.write_authorization_to_file();
-            return harvest;
+        Harvest {
+            authorization: authorization,
+            api_url: Harvest::default_api_url(),
+            id_url: Harvest::id_url(),
+            client: client,
+            retry_policy: RetryPolicy::default(),
         }
+    }
 
-        panic!("unable to authorize");
+    /* use an already obtained token, for example when testing against a local server */
+    pub fn from_token(token: String, account_id: u32) -> Harvest {
+        Harvest::from_authorization(Authorization::personal_access_token(token, account_id))
     }
 
-    fn read_authorization_from_file() -> Option<Harvest> {
-        match File::open(Harvest::config_file_path()) {
-            Ok(mut file) => {
-                let mut content = String::new();
-                file.read_to_string(&mut content).unwrap();
-                Some(
-                    serde_json::from_str(&content)
-                        .expect(&format!("Invalid configuration file: {}", content).to_string()),
-                )
-            }
-            Err(_) => None,
+    /* a personal access token in TFH_ACCESS_TOKEN and TFH_ACCOUNT_ID, for when the browser
+     * can not be used to sign in. It is never stored */
+    pub fn from_environment() -> Option<Harvest> {
+        let token = env::var("TFH_ACCESS_TOKEN").ok()?;
+        let account_id = env::var("TFH_ACCOUNT_ID").ok()?.trim().parse().ok()?;
+
+        if token.trim().is_empty() {
+            None
+        } else {
+            Some(Harvest::from_token(token.trim().to_string(), account_id))
         }
     }
 
-    fn write_authorization_to_file(&self) {
-        write(Harvest::config_file_path(), json!(self).to_string())
-            .expect("unable to save config file");
+    pub fn authorization(&self) -> &Authorization {
+        &self.authorization
     }
 
-    fn config_file_path() -> PathBuf {
-        let mut path = dirs::config_dir().expect("Unable to find XDG config dir path");
-        path.push(Harvest::CONFIG_FILE_NAME);
-        path
+    pub fn account_id(&self) -> u32 {
+        self.authorization.account_id
     }
 
-    fn authorize_callback(mut stream: TcpStream) -> (String, String, String) {
-        let mut buffer = [0; 512];
-        let mut first_line = "".to_string();
+    /* all following requests are sent for this account */
+    pub fn switch_account(&mut self, account_id: u32) {
+        self.authorization.account_id = account_id;
+    }
 
-        loop {
-            match stream.read(&mut buffer) {
-                Ok(n) => {
-                    if first_line == "" {
-                        let request = String::from_utf8_lossy(&buffer[..]).to_string();
-                        first_line = request.lines().next().unwrap().to_string();
-                    }
-                    if n < buffer.len() {
-                        break;
-                    }
-                }
-                Err(_) => {
-                    panic!("unable to read request");
-                }
-            }
+    /* one client for all requests, so connections are reused instead of set up for every click */
+    fn http_client(authorization: &Authorization) -> reqwest::blocking::Client {
+        let mut headers = reqwest::header::HeaderMap::new();
+        /* a token that is not a valid header value will simply be rejected by Harvest */
+        if let Ok(mut value) =
+            reqwest::header::HeaderValue::from_str(&format!("Bearer {}", authorization.token))
+        {
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
         }
 
-        let result = parse_account_details(&first_line);
+        reqwest::blocking::Client::builder()
+            .user_agent(Harvest::user_agent())
+            .default_headers(headers)
+            .connect_timeout(Harvest::CONNECT_TIMEOUT)
+            .timeout(Harvest::REQUEST_TIMEOUT)
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .expect("Unable to create HTTP client")
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Harvest {
+        self.retry_policy = retry_policy;
+        self
+    }
 
-        let response = "HTTP/1.1 200 OK\r\n\r\n<!DOCTYPE html>
-            <html>
-                <body>
-                    Authorized successfully
-                </body>
-            </html>\r\n";
+    pub fn with_api_url(mut self, api_url: &str) -> Harvest {
+        self.api_url = api_url.trim_end_matches('/').to_string();
+        self
+    }
 
-        stream.write(response.as_bytes()).unwrap();
-        stream.flush().unwrap();
+    pub fn with_id_url(mut self, id_url: &str) -> Harvest {
+        self.id_url = id_url.trim_end_matches('/').to_string();
+        self
+    }
 
-        result
+    /* can be overridden to use a proxy or a local stub instead of the real service */
+    fn default_api_url() -> String {
+        env::var("TFH_API_URL")
+            .unwrap_or(Harvest::API_URL.to_string())
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    pub(crate) fn id_url() -> String {
+        env::var("TFH_ID_URL")
+            .unwrap_or(Harvest::ID_URL.to_string())
+            .trim_end_matches('/')
+            .to_string()
     }
 
     pub fn user_agent() -> String {
@@ -269,58 +533,54 @@ impl Harvest {
         )
     }
 
-    pub fn active_project_assignments(&self) -> Vec<ProjectAssignment> {
-        let mut project_assignments: Vec<ProjectAssignment> = vec![];
-        let mut current_page = 1;
-
-        loop {
-            let url = format!(
-                "https://api.harvestapp.com/v2/users/me/project_assignments?page={}",
-                current_page
-            );
-            let res = self.api_get_request(&url);
-            let body = &res.unwrap().text().unwrap();
-            let page: ProjectAssignmentPage = serde_json::from_str(body).expect(
-                &format!("Unexpected project assignment page structure: {}", body).to_string(),
-            );
-
-            for project_assignment in page.project_assignments {
-                project_assignments.push(project_assignment);
-            }
+    pub fn active_project_assignments(&self) -> Result<Vec<ProjectAssignment>, HarvestError> {
+        let url = format!("{}/users/me/project_assignments", self.api_url);
+        self.api_get_all_pages::<ProjectAssignmentPage>(&url)
+    }
 
-            if current_page == page.total_pages {
-                break;
-            } else {
-                current_page += 1;
-            }
-        }
+    pub fn time_entries_for(
+        &self,
+        user: &User,
+        from: String,
+        till: String,
+    ) -> Result<Vec<TimeEntry>, HarvestError> {
+        let url = format!(
+            "{}/time_entries?user_id={}&from={}&to={}",
+            self.api_url, user.id, from, till
+        );
+        self.api_get_all_pages::<TimeEntryPage>(&url)
+    }
 
-        project_assignments
+    pub fn time_entry(&self, id: u32) -> Result<TimeEntry, HarvestError> {
+        let url = format!("{}/time_entries/{}", self.api_url, id);
+        Harvest::parse(self.api_get_request(&url)?)
     }
 
-    pub fn time_entries_for(&self, user: &User, from: String, till: String) -> Vec<TimeEntry> {
+    pub fn running_time_entries(&self, user: &User) -> Result<Vec<TimeEntry>, HarvestError> {
         let url = format!(
-            "https://api.harvestapp.com/v2/time_entries?user_id={}&from={}&to={}",
-            user.id, from, till
+            "{}/time_entries?user_id={}&is_running=true",
+            self.api_url, user.id
         );
-        match self.api_get_request(&url) {
-            Ok(res) => {
-                let body = &res.text().unwrap();
-                let page: TimeEntryPage = serde_json::from_str(body)
-                    .expect(&format!("Unexpected time entry page structure: {}", body).to_string());
+        self.api_get_all_pages::<TimeEntryPage>(&url)
+    }
 
-                page.time_entries
-            }
-            Err(_e) => Vec::new(),
-        }
+    /* the names of the granted accounts, these are not part of the authorization */
+    pub fn accounts(&self) -> Result<Vec<Account>, HarvestError> {
+        let url = format!("{}/api/v2/accounts", self.id_url);
+        /* not tied to one account, so without the Harvest-Account-Id header */
+        let response: AccountsResponse = Harvest::parse(self.send(self.client.get(&url), true)?)?;
+        let account_ids = self.authorization.account_ids();
+
+        Ok(response
+            .accounts
+            .into_iter()
+            .filter(|account| account.product == "harvest" && account_ids.contains(&account.id))
+            .collect())
     }
 
-    pub fn current_user(&self) -> User {
-        let url = "https://api.harvestapp.com/v2/users/me";
-        let res = self.api_get_request(&url);
-        let body = &res.unwrap().text().unwrap();
-        serde_json::from_str(body)
-            .expect(&format!("Unexpected user structure: {}", body).to_string())
+    pub fn current_user(&self) -> Result<User, HarvestError> {
+        let url = format!("{}/users/me", self.api_url);
+        Harvest::parse(self.api_get_request(&url)?)
     }
 
     pub fn start_timer(
@@ -330,8 +590,8 @@ impl Harvest {
         notes: String,
         hours: f32,
         now: &chrono::NaiveDate,
-    ) -> TimeEntry {
-        let url = "https://api.harvestapp.com/v2/time_entries";
+    ) -> Result<TimeEntry, HarvestError> {
+        let url = format!("{}/time_entries", self.api_url);
         let mut timer = Timer {
             id: None,
             project_id: project_id,
@@ -348,34 +608,19 @@ impl Harvest {
             timer.hours = Some(hours);
         }
 
-        let res = self.api_post_request(&url, &timer);
-        let body = &res.text().unwrap();
-        serde_json::from_str(body)
-            .expect(&format!("Unexpected timer structure: {}", body).to_string())
+        Harvest::parse(self.api_post_request(&url, &timer)?)
     }
 
-    pub fn restart_timer(&self, time_entry_id: u32) -> TimeEntry {
-        let url = format!(
-            "https://api.harvestapp.com/v2/time_entries/{}/restart",
-            time_entry_id
-        );
+    pub fn restart_timer(&self, time_entry_id: u32) -> Result<TimeEntry, HarvestError> {
+        let url = format!("{}/time_entries/{}/restart", self.api_url, time_entry_id);
 
-        let res = self.api_patch_request(&url, &());
-        let body = &res.text().unwrap();
-        serde_json::from_str(body)
-            .expect(&format!("Unexpected timer structure: {}", body).to_string())
+        Harvest::parse(self.api_patch_request(&url, &())?)
     }
 
-    pub fn stop_timer(&self, time_entry_id: u32) -> TimeEntry {
-        let url = format!(
-            "https://api.harvestapp.com/v2/time_entries/{}/stop",
-            time_entry_id
-        );
+    pub fn stop_timer(&self, time_entry_id: u32) -> Result<TimeEntry, HarvestError> {
+        let url = format!("{}/time_entries/{}/stop", self.api_url, time_entry_id);
 
-        let res = self.api_patch_request(&url, &());
-        let body = &res.text().unwrap();
-        serde_json::from_str(body)
-            .expect(&format!("Unexpected timer structure: {}", body).to_string())
+        Harvest::parse(self.api_patch_request(&url, &())?)
     }
 
     pub fn update_timer(
@@ -387,8 +632,8 @@ impl Harvest {
         hours: f32,
         is_running: bool,
         spent_date: String,
-    ) -> TimeEntry {
-        let url = format!("https://api.harvestapp.com/v2/time_entries/{}", id);
+    ) -> Result<TimeEntry, HarvestError> {
+        let url = format!("{}/time_entries/{}", self.api_url, id);
 
         /* TODO how not to sent hours when is_running in a better way? */
         if is_running {
@@ -401,10 +646,7 @@ impl Harvest {
                 spent_date: Some(spent_date),
             };
 
-            let res = self.api_patch_request(&url, &t2);
-            let body = &res.text().unwrap();
-            serde_json::from_str(body)
-                .expect(&format!("Unexpected time entry structure: {}", body).to_string())
+            Harvest::parse(self.api_patch_request(&url, &t2)?)
         } else {
             let timer = Timer {
                 id: Some(id),
@@ -415,77 +657,147 @@ impl Harvest {
                 hours: Some(hours),
                 spent_date: Some(spent_date),
             };
-            let res = self.api_patch_request(&url, &timer);
-            let body = &res.text().unwrap();
-            serde_json::from_str(body)
-                .expect(&format!("Unexpected time entry structure: {}", body).to_string())
+            Harvest::parse(self.api_patch_request(&url, &timer)?)
         }
     }
 
-    pub fn delete_timer(&self, timer_id: u32) -> TimeEntry {
-        let url = format!("https://api.harvestapp.com/v2/time_entries/{}", timer_id);
+    pub fn delete_timer(&self, timer_id: u32) -> Result<TimeEntry, HarvestError> {
+        let url = format!("{}/time_entries/{}", self.api_url, timer_id);
+
+        Harvest::parse(self.api_delete_request(&url)?)
+    }
+
+    fn api_get_request(&self, url: &str) -> Result<reqwest::blocking::Response, HarvestError> {
+        self.send(self.request(reqwest::Method::GET, url), true)
+    }
 
-        let res = self.api_delete_request(&url);
-        let body = &res.text().unwrap();
-        serde_json::from_str(body)
-            .expect(&format!("Unexpected timer structure: {}", body).to_string())
+    /* the account can change after creating the client, so it is added to every request */
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::blocking::RequestBuilder {
+        self.client
+            .request(method, url)
+            .header("Harvest-Account-Id", self.authorization.account_id)
     }
 
-    fn api_get_request(&self, url: &str) -> Result<reqwest::blocking::Response, reqwest::Error> {
-        let client = reqwest::blocking::Client::new();
+    /* follows the next links until all records of a list endpoint are retrieved */
+    fn api_get_all_pages<P: Page>(&self, url: &str) -> Result<Vec<P::Record>, HarvestError> {
+        let mut records = vec![];
+        let mut next_url = Some(url.to_string());
+
+        while let Some(url) = next_url {
+            let page: P = Harvest::parse(self.api_get_request(&url)?)?;
+            /* links always point to the real API, keep using a configured proxy or stub */
+            next_url = page
+                .next_page()
+                .map(|next| next.replacen(Harvest::API_URL, &self.api_url, 1));
+            records.append(&mut page.into_records());
+        }
 
-        client
-            .get(url)
-            .header("Authorization", format!("Bearer {}", self.token))
-            .header("Harvest-Account-Id", format!("{}", self.account_id))
-            .header("User-Agent", Harvest::user_agent())
-            .send()
+        Ok(records)
     }
 
     fn api_post_request<T: serde::Serialize + ?Sized>(
         &self,
         url: &str,
         json: &T,
-    ) -> reqwest::blocking::Response {
-        let client = reqwest::blocking::Client::new();
-
-        client
-            .post(url)
-            .json(&json)
-            .header("Authorization", format!("Bearer {}", self.token))
-            .header("Harvest-Account-Id", format!("{}", self.account_id))
-            .header("User-Agent", Harvest::user_agent())
-            .send()
-            .unwrap()
+    ) -> Result<reqwest::blocking::Response, HarvestError> {
+        /* never retried when the request might have been processed, to prevent double timers */
+        self.send(self.request(reqwest::Method::POST, url).json(&json), false)
     }
 
-    fn api_delete_request(&self, url: &str) -> reqwest::blocking::Response {
-        let client = reqwest::blocking::Client::new();
-
-        client
-            .delete(url)
-            .header("Authorization", format!("Bearer {}", self.token))
-            .header("Harvest-Account-Id", format!("{}", self.account_id))
-            .header("User-Agent", Harvest::user_agent())
-            .send()
-            .unwrap()
+    fn api_delete_request(&self, url: &str) -> Result<reqwest::blocking::Response, HarvestError> {
+        self.send(self.request(reqwest::Method::DELETE, url), true)
     }
 
     fn api_patch_request<T: serde::Serialize + ?Sized>(
         &self,
         url: &str,
         json: &T,
-    ) -> reqwest::blocking::Response {
-        let client = reqwest::blocking::Client::new();
-
-        client
-            .patch(url)
-            .json(&json)
-            .header("Authorization", format!("Bearer {}", self.token))
-            .header("Harvest-Account-Id", format!("{}", self.account_id))
-            .header("User-Agent", Harvest::user_agent())
-            .send()
-            .unwrap()
+    ) -> Result<reqwest::blocking::Response, HarvestError> {
+        self.send(self.request(reqwest::Method::PATCH, url).json(&json), true)
+    }
+
+    /* sends the request, retrying rate limited requests and, when it is safe to repeat the
+     * request, server and connection errors */
+    fn send(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+        idempotent: bool,
+    ) -> Result<reqwest::blocking::Response, HarvestError> {
+        let mut attempt = 0;
+
+        loop {
+            /* only streaming bodies cannot be cloned, we always send json */
+            let result = request
+                .try_clone()
+                .expect("Request body can not be cloned")
+                .send();
+
+            let (retry, retry_after) = match &result {
+                Ok(res) if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    (true, Harvest::retry_after(res))
+                }
+                Ok(res) if res.status().is_server_error() => {
+                    (idempotent, Harvest::retry_after(res))
+                }
+                Ok(_) => (false, None),
+                /* the request did not reach Harvest, so it is always safe to try again */
+                Err(e) if e.is_connect() => (true, None),
+                Err(_) => (idempotent, None),
+            };
+
+            let delay = retry_after.unwrap_or(self.retry_policy.initial_delay * 2u32.pow(attempt));
+            if !retry
+                || attempt >= self.retry_policy.max_retries
+                || delay > self.retry_policy.max_delay
+            {
+                return Harvest::check_status(result?);
+            }
+
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    fn retry_after(res: &reqwest::blocking::Response) -> Option<Duration> {
+        res.headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs)
+    }
+
+    /* turn unsuccessful responses into errors, using the message Harvest supplies if any */
+    fn check_status(
+        res: reqwest::blocking::Response,
+    ) -> Result<reqwest::blocking::Response, HarvestError> {
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(HarvestError::AuthExpired);
+        }
+
+        let body = res.text()?;
+        let message = match serde_json::from_str::<ErrorBody>(&body) {
+            Ok(ErrorBody {
+                message: Some(message),
+                ..
+            }) => message,
+            Ok(ErrorBody {
+                error_description: Some(description),
+                ..
+            }) => description,
+            _ => status.canonical_reason().unwrap_or("").to_string(),
+        };
+        Err(HarvestError::Status(status.as_u16(), message))
+    }
+
+    fn parse<T: serde::de::DeserializeOwned>(
+        res: reqwest::blocking::Response,
+    ) -> Result<T, HarvestError> {
+        let body = res.text()?;
+        serde_json::from_str(&body).map_err(|e| HarvestError::Deserialize(e, body))
     }
 }
 
@@ -525,39 +837,162 @@ pub fn f32_to_duration_str(duration: f32) -> String {
     format!("{:.0}:{:0>2.0}", hours, minutes * 60.0)
 }
 
-/* TODO improve this messy parse function */
-pub fn parse_account_details(request: &str) -> (String, String, String) {
-    let mut parts = request.split(" ");
+/* like f32_to_duration_str, for a timer ticking every second */
+pub fn f32_to_duration_with_seconds_str(duration: f32) -> String {
+    let seconds = (duration.max(0.0) * 3600.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/* what Harvest redirected the browser back with after signing in */
+#[derive(Debug, PartialEq)]
+pub enum AuthCallback {
+    Authorized {
+        token: String,
+        account_ids: Vec<u32>,
+        expires_in: u64,
+    },
+    /* the user denied access or Harvest refused the request */
+    Error {
+        error: String,
+        description: Option<String>,
+    },
+}
+
+/* the query parameters of the request line */
+fn redirect_params(request: &str) -> Result<HashMap<String, String>, HarvestError> {
+    let mut parts = request.split(' ');
     parts.next(); /* GET */
-    let uri = parts.next().unwrap().parse::<hyper::Uri>().unwrap();
-    let parts = uri.query().unwrap().split("&");
-    let mut access_token = "";
-    let mut account_id = "";
-    let mut expires_in = "";
-
-    for part in parts {
-        if part.starts_with("access_token") {
-            let mut parts = part.split("=");
-            parts.next();
-            access_token = parts.next().unwrap();
-        } else if part.starts_with("scope") {
-            let mut parts = part.split("=");
-            parts.next();
-            account_id = parts.next().unwrap();
-            let mut parts = account_id.split("%3A");
-            parts.next();
-            account_id = parts.next().unwrap();
-        } else if part.starts_with("expires_in") {
-            let mut parts = part.split("=");
-            parts.next();
-            expires_in = parts.next().unwrap();
+    let uri = parts
+        .next()
+        .unwrap_or("")
+        .parse::<hyper::Uri>()
+        .map_err(|_| HarvestError::Login("Invalid redirect from Harvest".to_string()))?;
+
+    let mut params = HashMap::new();
+    for pair in uri.query().unwrap_or("").split('&') {
+        let mut pair = pair.splitn(2, '=');
+        let key = percent_decode(pair.next().unwrap_or(""));
+        let value = percent_decode(pair.next().unwrap_or(""));
+        params.insert(key, value);
+    }
+    Ok(params)
+}
+
+/* whether the request line carries the state sent along in the authorize url */
+pub fn is_redirect_for(request: &str, state: &str) -> bool {
+    redirect_params(request)
+        .map(|params| params.get("state").map(String::as_str) == Some(state))
+        .unwrap_or(false)
+}
+
+/* parses the request line of the OAuth redirect, state must match the one in the authorize url */
+pub fn parse_account_details(request: &str, state: &str) -> Result<AuthCallback, HarvestError> {
+    let invalid = |message: &str| HarvestError::Login(message.to_string());
+    let mut params = redirect_params(request)?;
+
+    /* without it any web page could make us use its token */
+    if params.get("state").map(String::as_str) != Some(state) {
+        return Err(invalid(
+            "The redirect from Harvest does not belong to this sign in",
+        ));
+    }
+
+    if let Some(error) = params.remove("error") {
+        return Ok(AuthCallback::Error {
+            error: error,
+            description: params.remove("error_description"),
+        });
+    }
+
+    let token = match params.remove("access_token") {
+        Some(token) if !token.is_empty() => token,
+        _ => return Err(invalid("Harvest did not provide an access token")),
+    };
+    /* space separated, for example "harvest:123 harvest:456 forecast:789" */
+    let account_ids: Vec<u32> = params
+        .get("scope")
+        .map(String::as_str)
+        .unwrap_or("")
+        .split_whitespace()
+        .filter_map(|scope| scope.strip_prefix("harvest:"))
+        .filter_map(|id| id.parse().ok())
+        .collect();
+    if account_ids.is_empty() {
+        return Err(invalid("Harvest did not grant access to an account"));
+    }
+    let expires_in = params
+        .get("expires_in")
+        .and_then(|expires_in| expires_in.parse().ok())
+        .ok_or_else(|| invalid("Harvest did not provide a valid expiry"))?;
+
+    Ok(AuthCallback::Authorized {
+        token: token,
+        account_ids: account_ids,
+        expires_in: expires_in,
+    })
+}
+
+/* decodes a query string component, invalid escapes are kept as is */
+fn percent_decode(subject: &str) -> String {
+    let bytes = subject.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len()
+                && bytes[i + 1].is_ascii_hexdigit()
+                && bytes[i + 2].is_ascii_hexdigit() =>
+            {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
         }
+        i += 1;
     }
-    (
-        access_token.to_string(),
-        account_id.to_string(),
-        expires_in.to_string(),
-    )
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/* how projects and tasks are searched, in the popup as well as on the command line */
+pub fn fuzzy_matches(subject: &str, key: &str) -> bool {
+    subject.to_lowercase().contains(&key.to_lowercase())
+}
+
+/* for files kept between runs, notes might contain private details */
+pub(crate) fn write_private_json<T: serde::Serialize + ?Sized>(
+    path: &std::path::Path,
+    value: &T,
+) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+
+    /* only applies when created, so the file itself is made private as well */
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path.parent().unwrap())?;
+
+    /* written next to it first, so a crash never leaves a half written file behind */
+    let temp_path = path.with_extension("json.tmp");
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp_path)?;
+    /* left behind by a crash of an earlier version */
+    std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(serde_json::to_string(value).unwrap().as_bytes())?;
+    std::fs::rename(temp_path, path)
 }
 
 pub fn escape_html(subject: &str) -> String {
@@ -0,0 +1,232 @@
+use crate::{
+    escape_html, is_redirect_for, parse_account_details, AuthCallback, Authorization, Harvest,
+    HarvestError,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/* receives the OAuth redirect from the browser on a local port */
+pub struct LoginServer {
+    listener: TcpListener,
+    port: u16,
+    /* sent along to Harvest, a redirect without it was not caused by us */
+    state: String,
+}
+
+impl LoginServer {
+    /* the port registered as redirect for our client id, Harvest refuses any other */
+    pub const DEFAULT_PORT: u16 = 12345;
+    pub const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+    const READ_TIMEOUT: Duration = Duration::from_secs(2);
+    const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+    pub fn bind() -> Result<LoginServer, HarvestError> {
+        LoginServer::bind_to(LoginServer::DEFAULT_PORT)
+    }
+
+    pub fn bind_to(port: u16) -> Result<LoginServer, HarvestError> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| {
+            HarvestError::Login(format!(
+                "Port {} is in use, it is needed to receive the authorization from Harvest. \
+                 Close the program using it and try again: {}",
+                port, e
+            ))
+        })?;
+        let port = listener.local_addr().map(|a| a.port()).unwrap_or(port);
+
+        Ok(LoginServer {
+            listener: listener,
+            port: port,
+            state: LoginServer::random_state(),
+        })
+    }
+
+    /* std has no random generator, but every RandomState is seeded with random keys */
+    fn random_state() -> String {
+        (0..2)
+            .map(|_| {
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write_u128(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_nanos(),
+                );
+                format!("{:016x}", hasher.finish())
+            })
+            .collect()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    pub fn authorize_url(&self) -> String {
+        format!(
+            "{}/oauth2/authorize?client_id={}&response_type=token&state={}",
+            Harvest::id_url(),
+            Harvest::CLIENT_ID,
+            self.state
+        )
+    }
+
+    /* blocks until the browser is redirected back, the timeout passes or cancelled is set */
+    pub fn wait(
+        &self,
+        timeout: Duration,
+        cancelled: &AtomicBool,
+    ) -> Result<Authorization, HarvestError> {
+        let login_error = |e: std::io::Error| HarvestError::Login(e.to_string());
+        let started_at = Instant::now();
+        self.listener.set_nonblocking(true).map_err(login_error)?;
+
+        loop {
+            if cancelled.load(Ordering::SeqCst) {
+                return Err(HarvestError::LoginCancelled);
+            }
+            if started_at.elapsed() > timeout {
+                return Err(HarvestError::Login(
+                    "Timed out waiting for the authorization from Harvest".to_string(),
+                ));
+            }
+
+            match self.listener.accept() {
+                Ok((stream, _address)) => {
+                    if let Some(result) = self.handle_connection(stream) {
+                        return result;
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(LoginServer::POLL_INTERVAL);
+                }
+                Err(e) => return Err(login_error(e)),
+            }
+        }
+    }
+
+    /* returns None for requests that are not the redirect, such as a favicon or the redirect of
+     * an earlier sign in */
+    fn handle_connection(
+        &self,
+        mut stream: TcpStream,
+    ) -> Option<Result<Authorization, HarvestError>> {
+        let request = match LoginServer::read_request(&mut stream) {
+            Ok(request) => request,
+            Err(_) => return None,
+        };
+        let request_line = request.lines().next().unwrap_or("");
+        let mut parts = request_line.split(' ');
+        let method = parts.next().unwrap_or("");
+        let target = parts.next().unwrap_or("");
+
+        if method != "GET" || !(target == "/" || target.starts_with("/?")) {
+            LoginServer::respond(&mut stream, "404 Not Found", "Not found");
+            return None;
+        }
+        if !is_redirect_for(request_line, &self.state) {
+            LoginServer::respond(
+                &mut stream,
+                "400 Bad Request",
+                "This page does not belong to the current sign in, use the page opened last.",
+            );
+            return None;
+        }
+
+        let result = self.authorization_from(request_line);
+        match &result {
+            Ok(_) => LoginServer::respond(
+                &mut stream,
+                "200 OK",
+                "Signed in to Harvest successfully. You can close this page and return to Timer for Harvest.",
+            ),
+            Err(e) => LoginServer::respond(
+                &mut stream,
+                "400 Bad Request",
+                &escape_html(&e.to_string()),
+            ),
+        }
+        Some(result)
+    }
+
+    fn authorization_from(&self, request_line: &str) -> Result<Authorization, HarvestError> {
+        match parse_account_details(request_line, &self.state)? {
+            AuthCallback::Authorized {
+                token,
+                account_ids,
+                expires_in,
+            } => {
+                let unix_timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+
+                Ok(Authorization {
+                    token: token,
+                    account_id: account_ids[0],
+                    expires_at: unix_timestamp + expires_in,
+                    account_ids: account_ids,
+                })
+            }
+            AuthCallback::Error { error, description } => {
+                Err(HarvestError::Login(description.unwrap_or(error)))
+            }
+        }
+    }
+
+    /* reads up to the end of the headers, the redirect has no body */
+    fn read_request(stream: &mut TcpStream) -> std::io::Result<String> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(LoginServer::READ_TIMEOUT))?;
+
+        let mut request = vec![];
+        let mut buffer = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n")
+            && request.len() < LoginServer::MAX_REQUEST_SIZE
+        {
+            let n = stream.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..n]);
+        }
+
+        Ok(String::from_utf8_lossy(&request).to_string())
+    }
+
+    fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+        let body = format!(
+            "<!DOCTYPE html>
+<html>
+    <head>
+        <meta charset=\"utf-8\">
+        <title>Timer for Harvest</title>
+    </head>
+    <body style=\"font-family: sans-serif; text-align: center; margin-top: 4em;\">
+        <p>{}</p>
+    </body>
+</html>
+",
+            message
+        );
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+
+        /* the browser going away is not a reason to fail the login */
+        let _ = stream.write_all(response.as_bytes());
+        let _ = stream.flush();
+    }
+}
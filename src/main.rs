@@ -1,11 +1,16 @@
 mod app;
 mod popup;
+mod sign_in;
+mod tray;
 mod ui;
 
 use app::App;
 use std::env::args;
 use std::sync::mpsc;
-use timer_for_harvest::Harvest;
+use timer_for_harvest::cache::Cache;
+use timer_for_harvest::cli::{self, Cli};
+use timer_for_harvest::journal::Journal;
+use timer_for_harvest::{credentials, Harvest};
 use ui::Ui;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -13,15 +18,33 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if args.len() == 2 && &args[1] == "--version" {
         println!("{}", Harvest::user_agent());
+    } else if args.len() == 2 && &args[1] == "--logout" {
+        credentials::clear_all()?;
+        Journal::new(Journal::default_path()).clear()?;
+        Cache::new(Cache::default_dir()).clear()?;
+        println!("Signed out of Harvest");
+    } else if args.len() >= 2 && cli::COMMANDS.contains(&args[1].as_str()) {
+        /* runs without a display, for use in scripts */
+        std::process::exit(Cli::main(&args[1..]));
     } else {
+        /* used by the quick actions in the desktop file */
+        let action = match args.as_slice() {
+            [_, option, name] if option == "--action" => Some(name.clone()),
+            _ => None,
+        };
+        let application = Ui::application();
+        if Ui::hand_over_to_running_instance(&application, &action) {
+            return Ok(());
+        }
+
         let (to_ui, from_app) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
         let (to_app, from_ui) = mpsc::channel();
 
-        let app = App::new(to_ui);
-        let ui = Ui::new(to_app);
+        let app = App::new(to_ui, to_app.clone());
+        let ui = Ui::new(application, to_app);
 
         App::handle_ui_signals(app, from_ui);
-        Ui::handle_app_signals(ui, from_app);
+        Ui::handle_app_signals(ui, from_app, action);
     }
 
     Ok(())
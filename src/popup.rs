@@ -31,6 +31,8 @@ pub struct Popup {
     notes_input: gtk::TextView,
     hours_input: gtk::Entry,
     time_entry_id: Option<u32>,
+    conflict_bar: gtk::InfoBar,
+    conflict_label: gtk::Label,
 }
 
 impl Popup {
@@ -89,6 +91,23 @@ impl Popup {
             }
         }));
 
+        let conflict_bar = gtk::InfoBar::new();
+        conflict_bar.set_message_type(gtk::MessageType::Warning);
+        conflict_bar.add_button("Merge", gtk::ResponseType::Apply);
+        conflict_bar.add_button("Overwrite", gtk::ResponseType::Accept);
+        conflict_bar.add_button("Discard", gtk::ResponseType::Reject);
+        /* only shown by show_conflict */
+        conflict_bar.set_no_show_all(true);
+        let conflict_label = gtk::Label::new(None);
+        conflict_label.set_line_wrap(true);
+        conflict_label.set_xalign(0.0);
+        conflict_bar
+            .get_content_area()
+            .unwrap()
+            .downcast::<gtk::Container>()
+            .unwrap()
+            .add(&conflict_label);
+
         let popup = Popup {
             window: window,
             project_chooser: Popup::project_chooser(project_assignments),
@@ -99,6 +118,8 @@ impl Popup {
             notes_input: notes_input,
             hours_input: hours_input,
             time_entry_id: None,
+            conflict_bar: conflict_bar,
+            conflict_label: conflict_label,
         };
         popup.add_widgets();
         popup
@@ -181,15 +202,16 @@ impl Popup {
         scrollable_window.add(&self.notes_input);
         scrollable_window.set_shadow_type(gtk::ShadowType::Out);
 
-        grid.attach(&self.project_chooser, 0, 0, 2, 1);
-        grid.attach(&self.task_chooser, 0, 1, 2, 1);
-        grid.attach(&scrollable_window, 0, 2, 2, 6);
-        grid.attach(&self.hours_input, 1, 8, 1, 1);
+        grid.attach(&self.conflict_bar, 0, 0, 2, 1);
+        grid.attach(&self.project_chooser, 0, 1, 2, 1);
+        grid.attach(&self.task_chooser, 0, 2, 2, 1);
+        grid.attach(&scrollable_window, 0, 3, 2, 6);
+        grid.attach(&self.hours_input, 1, 9, 1, 1);
 
         self.delete_button.set_sensitive(false);
-        grid.attach(&self.delete_button, 0, 9, 1, 2);
+        grid.attach(&self.delete_button, 0, 10, 1, 2);
 
-        grid.attach(&self.save_button, 1, 9, 1, 2);
+        grid.attach(&self.save_button, 1, 10, 1, 2);
         self.save_button.grab_default();
 
         grid.set_column_homogeneous(true);
@@ -322,6 +344,63 @@ impl Popup {
         });
     }
 
+    pub fn set_hours(&self, hours: f32) {
+        self.hours_input.set_text(&f32_to_duration_str(hours));
+    }
+
+    /* shows the edit that could not be saved, the user chooses what to do with it */
+    pub fn show_conflict(&self, theirs: &TimeEntry) {
+        let id = match self.time_entry_id {
+            Some(id) => id,
+            None => return,
+        };
+
+        self.window.set_title("Time entry changed in Harvest");
+        self.conflict_label.set_markup(&format!(
+            "This time entry was changed in Harvest before your changes were saved. \
+             In Harvest it now is:\n<b>{}</b> ({})\n{} - {} - {}",
+            escape_html(&theirs.project.name_and_code()),
+            escape_html(&theirs.client.name),
+            escape_html(&theirs.task.name),
+            escape_html(theirs.notes.as_deref().unwrap_or("")),
+            f32_to_duration_str(theirs.hours)
+        ));
+
+        /* the choice is about these changes, making more is done afterwards */
+        self.project_chooser.set_sensitive(false);
+        self.task_chooser.set_sensitive(false);
+        self.notes_input.set_sensitive(false);
+        self.hours_input.set_sensitive(false);
+        self.save_button.hide();
+        self.delete_button.hide();
+
+        let to_app = self.to_app.clone();
+        let window = self.window.clone();
+        self.conflict_bar.connect_response(move |_bar, response| {
+            let resolution = match response {
+                gtk::ResponseType::Apply => app::Resolution::Merge,
+                gtk::ResponseType::Accept => app::Resolution::Overwrite,
+                _ => app::Resolution::Discard,
+            };
+            to_app
+                .send(app::Signal::ResolveConflict(id, resolution))
+                .expect("Sending message to application thread");
+            /* unlike close, does not emit delete-event */
+            window.destroy();
+        });
+        /* closing the window keeps what is in Harvest */
+        let to_app = self.to_app.clone();
+        self.window.connect_delete_event(move |_, _| {
+            to_app
+                .send(app::Signal::ResolveConflict(id, app::Resolution::Discard))
+                .expect("Sending message to application thread");
+            Inhibit(false)
+        });
+
+        self.conflict_bar.set_no_show_all(false);
+        self.conflict_bar.show_all();
+    }
+
     fn fuzzy_matching(completion: &gtk::EntryCompletion, key: &str, iter: &gtk::TreeIter) -> bool {
         let store = completion.get_model().unwrap();
         let column_number = completion.get_text_column();
@@ -330,12 +409,7 @@ impl Popup {
             .get::<String>()
             .unwrap();
 
-        /* key is already lower case */
-        if row.to_lowercase().contains(key) {
-            true
-        } else {
-            false
-        }
+        fuzzy_matches(&row, key)
     }
 
     fn id_from_combo_box(combo_box: &gtk::ComboBox, index: u32) -> u32 {
@@ -0,0 +1,248 @@
+use crate::TimeEntry;
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::RequestNameReply;
+use dbus::blocking::Connection;
+use dbus::Message;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/* the application id itself is owned by GTK for activating the running instance */
+pub const NAME: &str = "nl.frankgroeneveld.TimerForHarvest";
+pub const PATH: &str = "/nl/frankgroeneveld/TimerForHarvest";
+pub const INTERFACE: &str = "nl.frankgroeneveld.TimerForHarvest";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="nl.frankgroeneveld.TimerForHarvest">
+    <method name="StartTimer">
+      <arg name="project_id" type="u" direction="in"/>
+      <arg name="task_id" type="u" direction="in"/>
+      <arg name="notes" type="s" direction="in"/>
+    </method>
+    <method name="StopRunning"/>
+    <method name="ToggleLast"/>
+    <method name="GetRunning">
+      <arg name="time_entry" type="a{sv}" direction="out"/>
+    </method>
+    <signal name="TimerStarted">
+      <arg name="time_entry" type="a{sv}"/>
+    </signal>
+    <signal name="TimerStopped">
+      <arg name="time_entry" type="a{sv}"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml_data" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+    <method name="GetMachineId">
+      <arg name="machine_uuid" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>"#;
+
+/* method calls that change timers, handled by the application */
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    StartTimer(u32, u32, String),
+    StopRunning,
+    ToggleLast,
+}
+
+/* exports timer control on the session bus, for shell extensions, shortcuts and scripts */
+pub struct Service {
+    to_service: mpsc::Sender<Option<TimeEntry>>,
+}
+
+impl Service {
+    /* how long it takes at most before a change of the running timer is signalled */
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /* on the session bus, or the bus at the given address */
+    pub fn start<F>(address: Option<String>, on_request: F) -> Result<Service, dbus::Error>
+    where
+        F: Fn(Request) + Send + 'static,
+    {
+        let (to_service, from_app) = mpsc::channel();
+        let (started, result) = mpsc::channel();
+
+        thread::spawn(move || {
+            let connection = match Service::connect(address) {
+                Ok(connection) => {
+                    let _ = started.send(Ok(()));
+                    connection
+                }
+                Err(e) => {
+                    let _ = started.send(Err(e));
+                    return;
+                }
+            };
+            let mut running: Option<TimeEntry> = None;
+
+            loop {
+                let message = match connection
+                    .channel()
+                    .blocking_pop_message(Service::POLL_INTERVAL)
+                {
+                    Ok(message) => message,
+                    Err(_) => return,
+                };
+
+                /* before replying, so GetRunning answers with what was sent last */
+                loop {
+                    match from_app.try_recv() {
+                        Ok(time_entry) => {
+                            Service::running_changed(&connection, &running, &time_entry);
+                            running = time_entry;
+                        }
+                        Err(mpsc::TryRecvError::Empty) => break,
+                        /* the application quit */
+                        Err(mpsc::TryRecvError::Disconnected) => return,
+                    }
+                }
+
+                if let Some(message) = message {
+                    if let Some(reply) = Service::reply(&message, &running, &on_request) {
+                        let _ = connection.channel().send(reply);
+                    }
+                }
+            }
+        });
+
+        match result.recv() {
+            Ok(Ok(())) => Ok(Service {
+                to_service: to_service,
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(dbus::Error::new_failed("D-Bus service stopped")),
+        }
+    }
+
+    /* the running timer of today, None when no timer is running */
+    pub fn set_running(&self, time_entry: Option<&TimeEntry>) {
+        /* nothing to do when the service stopped */
+        let _ = self.to_service.send(time_entry.cloned());
+    }
+
+    fn connect(address: Option<String>) -> Result<Connection, dbus::Error> {
+        let connection = match address {
+            Some(address) => Connection::new_address(&address)?,
+            None => Connection::new_session()?,
+        };
+        match connection.request_name(NAME, false, false, true)? {
+            RequestNameReply::PrimaryOwner => Ok(connection),
+            _ => Err(dbus::Error::new_failed(&format!(
+                "{} is owned by another process",
+                NAME
+            ))),
+        }
+    }
+
+    fn running_changed(
+        connection: &Connection,
+        previous: &Option<TimeEntry>,
+        current: &Option<TimeEntry>,
+    ) {
+        let previous_id = previous.as_ref().map(|t| t.id);
+        let current_id = current.as_ref().map(|t| t.id);
+        if previous_id == current_id {
+            return;
+        }
+
+        if let Some(time_entry) = previous {
+            Service::emit(connection, "TimerStopped", time_entry);
+        }
+        if let Some(time_entry) = current {
+            Service::emit(connection, "TimerStarted", time_entry);
+        }
+    }
+
+    fn emit(connection: &Connection, name: &str, time_entry: &TimeEntry) {
+        let signal = Message::new_signal(PATH, INTERFACE, name)
+            .expect("Valid D-Bus signal")
+            .append1(Service::properties(Some(time_entry)));
+        let _ = connection.channel().send(signal);
+    }
+
+    fn reply<F>(message: &Message, running: &Option<TimeEntry>, on_request: &F) -> Option<Message>
+    where
+        F: Fn(Request),
+    {
+        if message.msg_type() != dbus::MessageType::MethodCall {
+            return None;
+        }
+        let path = message.path().map(|p| p.to_string()).unwrap_or_default();
+        let interface = message.interface().map(|i| i.to_string());
+        let member = message.member().map(|m| m.to_string()).unwrap_or_default();
+        if path != PATH {
+            return dbus::channel::default_reply(message);
+        }
+
+        match (interface.as_deref(), member.as_str()) {
+            (Some("org.freedesktop.DBus.Introspectable"), "Introspect") => {
+                Some(message.method_return().append1(INTROSPECTION))
+            }
+            (Some(INTERFACE), "StartTimer") | (None, "StartTimer") => {
+                match message.read3::<u32, u32, &str>() {
+                    Ok((project_id, task_id, notes)) => {
+                        on_request(Request::StartTimer(project_id, task_id, notes.to_string()));
+                        Some(message.method_return())
+                    }
+                    Err(e) => Some(message.error(
+                        &"org.freedesktop.DBus.Error.InvalidArgs".into(),
+                        &std::ffi::CString::new(e.to_string()).unwrap(),
+                    )),
+                }
+            }
+            (Some(INTERFACE), "StopRunning") | (None, "StopRunning") => {
+                on_request(Request::StopRunning);
+                Some(message.method_return())
+            }
+            (Some(INTERFACE), "ToggleLast") | (None, "ToggleLast") => {
+                on_request(Request::ToggleLast);
+                Some(message.method_return())
+            }
+            (Some(INTERFACE), "GetRunning") | (None, "GetRunning") => Some(
+                message
+                    .method_return()
+                    .append1(Service::properties(running.as_ref())),
+            ),
+            _ => dbus::channel::default_reply(message),
+        }
+    }
+
+    /* empty when no timer is running */
+    fn properties(time_entry: Option<&TimeEntry>) -> PropMap {
+        let mut properties = PropMap::new();
+        let time_entry = match time_entry {
+            Some(time_entry) => time_entry,
+            None => return properties,
+        };
+
+        let mut insert = |key: &str, value: Box<dyn RefArg>| {
+            properties.insert(key.to_string(), Variant(value));
+        };
+        insert("id", Box::new(time_entry.id));
+        insert("spent_date", Box::new(time_entry.spent_date.clone()));
+        insert("project_id", Box::new(time_entry.project.id));
+        insert("project", Box::new(time_entry.project.name.clone()));
+        insert(
+            "project_code",
+            Box::new(time_entry.project.code.clone().unwrap_or_default()),
+        );
+        insert("client", Box::new(time_entry.client.name.clone()));
+        insert("task_id", Box::new(time_entry.task.id));
+        insert("task", Box::new(time_entry.task.name.clone()));
+        insert(
+            "notes",
+            Box::new(time_entry.notes.clone().unwrap_or_default()),
+        );
+        insert("hours", Box::new(time_entry.hours as f64));
+        properties
+    }
+}
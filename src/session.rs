@@ -0,0 +1,150 @@
+use crate::idle::Away;
+use dbus::arg::{prop_cast, OwnedFd, PropMap};
+use dbus::blocking::Connection;
+use dbus::Message;
+use dbus::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const LOGIND: &str = "org.freedesktop.login1";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+const SESSION: &str = "org.freedesktop.login1.Session";
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Locked,
+    /* since the screen was locked */
+    Unlocked(Away),
+    /* since the computer went to sleep */
+    Resumed(Away),
+}
+
+/* sleep and screen locks reported by systemd-logind */
+pub struct SessionMonitor {
+    connection: Connection,
+    /* missing when not started from a logind session, only sleep is reported then */
+    session: Option<Path<'static>>,
+}
+
+impl SessionMonitor {
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /* on the system bus, or the bus at the given address */
+    pub fn connect(address: Option<String>) -> Result<SessionMonitor, dbus::Error> {
+        let connection = match address {
+            Some(address) => Connection::new_address(&address)?,
+            None => Connection::new_system()?,
+        };
+
+        connection.add_match_no_cb(&format!(
+            "type='signal',sender='{}',path='{}',interface='{}',member='PrepareForSleep'",
+            LOGIND, MANAGER_PATH, MANAGER
+        ))?;
+        let proxy = connection.with_proxy(LOGIND, MANAGER_PATH, SessionMonitor::TIMEOUT);
+        let session: Option<(Path<'static>,)> = proxy
+            .method_call(MANAGER, "GetSessionByPID", (std::process::id(),))
+            .ok();
+        let session = session.map(|(path,)| path);
+        if let Some(path) = &session {
+            connection.add_match_no_cb(&format!(
+                "type='signal',sender='{}',path='{}',\
+                 interface='org.freedesktop.DBus.Properties',member='PropertiesChanged'",
+                LOGIND, path
+            ))?;
+        }
+
+        Ok(SessionMonitor {
+            connection: connection,
+            session: session,
+        })
+    }
+
+    pub fn watch<F>(self, on_event: F)
+    where
+        F: Fn(Event) + Send + 'static,
+    {
+        thread::spawn(move || {
+            /* delays sleeping until the time it starts is known, dropping it lets it start */
+            let mut _inhibitor = self.inhibit();
+            let mut asleep_since = None;
+            let mut locked_since = None;
+
+            loop {
+                let message = match self
+                    .connection
+                    .channel()
+                    .blocking_pop_message(Duration::from_secs(60))
+                {
+                    Ok(Some(message)) => message,
+                    Ok(None) => continue,
+                    Err(_) => return,
+                };
+
+                if let Some(sleeping) = self.sleeping(&message) {
+                    if sleeping {
+                        asleep_since = Some(SystemTime::now());
+                        _inhibitor = None;
+                    } else {
+                        _inhibitor = self.inhibit();
+                        if let Some(since) = asleep_since.take() {
+                            on_event(Event::Resumed(SessionMonitor::away(since)));
+                        }
+                    }
+                } else if let Some(locked) = self.locked(&message) {
+                    if locked && locked_since.is_none() {
+                        locked_since = Some(SystemTime::now());
+                        on_event(Event::Locked);
+                    } else if !locked {
+                        if let Some(since) = locked_since.take() {
+                            on_event(Event::Unlocked(SessionMonitor::away(since)));
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn inhibit(&self) -> Option<OwnedFd> {
+        let proxy = self
+            .connection
+            .with_proxy(LOGIND, MANAGER_PATH, SessionMonitor::TIMEOUT);
+        let result: Result<(OwnedFd,), dbus::Error> = proxy.method_call(
+            MANAGER,
+            "Inhibit",
+            (
+                "sleep",
+                "Timer for Harvest",
+                "Remembering when the timer should stop",
+                "delay",
+            ),
+        );
+        result.ok().map(|(fd,)| fd)
+    }
+
+    fn sleeping(&self, message: &Message) -> Option<bool> {
+        if &*message.interface()? != MANAGER || &*message.member()? != "PrepareForSleep" {
+            return None;
+        }
+        message.read1().ok()
+    }
+
+    fn locked(&self, message: &Message) -> Option<bool> {
+        let session = self.session.as_ref()?;
+        if message.path()? != *session || &*message.member()? != "PropertiesChanged" {
+            return None;
+        }
+        let (interface, changed): (&str, PropMap) = message.read2().ok()?;
+        if interface != SESSION {
+            return None;
+        }
+        prop_cast::<bool>(&changed, "LockedHint").copied()
+    }
+
+    fn away(since: SystemTime) -> Away {
+        Away {
+            since: since,
+            duration: SystemTime::now().duration_since(since).unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::app;
+use gtk::prelude::*;
+use std::sync::mpsc;
+
+/* handy gtk callback clone macro taken from https://gtk-rs.org/docs-src/tutorial/closures */
+macro_rules! clone {
+    (@param _) => ( _ );
+    (@param $x:ident) => ( $x );
+    ($($n:ident),+ => move || $body:expr) => (
+        {
+            $( let $n = $n.clone(); )+
+            move || $body
+        }
+    );
+    ($($n:ident),+ => move |$($p:tt),+| $body:expr) => (
+        {
+            $( let $n = $n.clone(); )+
+            move |$(clone!(@param $p),)+| $body
+        }
+    );
+}
+
+pub struct SignInWindow {
+    window: gtk::Window,
+    url_input: gtk::Entry,
+    status_label: gtk::Label,
+    open_button: gtk::Button,
+    retry_button: gtk::Button,
+    token_button: gtk::Button,
+}
+
+impl SignInWindow {
+    pub fn new(application: &gtk::Application, to_app: mpsc::Sender<app::Signal>) -> SignInWindow {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+
+        window.set_title("Sign in to Harvest");
+        window.set_default_size(450, 0);
+        window.set_modal(true);
+        window.set_type_hint(gdk::WindowTypeHint::Dialog);
+        window.set_border_width(18);
+        window.set_resizable(false);
+
+        /* closing the window by hand stops signing in, the application closes it using destroy */
+        window.connect_delete_event(clone!(to_app => move |_, _| {
+            to_app.send(app::Signal::CancelLogin)
+                .expect("Sending message to application thread");
+            Inhibit(false)
+        }));
+        window.add_events(gdk::EventMask::KEY_PRESS_MASK);
+        window.connect_key_press_event(|window, event| {
+            if event.get_keyval() == gdk::enums::key::Escape {
+                window.close();
+                Inhibit(true)
+            } else {
+                Inhibit(false)
+            }
+        });
+
+        window.set_transient_for(application.get_active_window().as_ref());
+        application.add_window(&window);
+
+        let grid = gtk::Grid::new();
+        grid.set_column_spacing(4);
+        grid.set_row_spacing(18);
+        window.add(&grid);
+
+        let title_label = gtk::Label::new(Some(&"<b>Sign in to Harvest</b>"));
+        title_label.set_use_markup(true);
+        title_label.set_xalign(0.0);
+        grid.attach(&title_label, 0, 0, 3, 1);
+
+        let explanation_label = gtk::Label::new(Some(
+            &"Timer for Harvest needs access to your Harvest account. Authorize it using a web \
+              browser, or copy the address below into a browser yourself.",
+        ));
+        explanation_label.set_line_wrap(true);
+        explanation_label.set_xalign(0.0);
+        explanation_label.set_max_width_chars(50);
+        grid.attach(&explanation_label, 0, 1, 3, 1);
+
+        let url_input = gtk::Entry::new();
+        url_input.set_editable(false);
+        url_input.set_hexpand(true);
+        grid.attach(&url_input, 0, 2, 2, 1);
+
+        let copy_button =
+            gtk::Button::new_from_icon_name(Some("edit-copy-symbolic"), gtk::IconSize::Button);
+        copy_button.set_tooltip_text(Some("Copy address"));
+        copy_button.connect_clicked(clone!(url_input => move |_button| {
+            let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+            clipboard.set_text(&url_input.get_text().unwrap());
+        }));
+        grid.attach(&copy_button, 2, 2, 1, 1);
+
+        let status_label = gtk::Label::new(None);
+        status_label.set_line_wrap(true);
+        status_label.set_xalign(0.0);
+        status_label.set_max_width_chars(50);
+
+        let open_button = gtk::Button::new_with_label("Open browser");
+        open_button
+            .get_style_context()
+            .add_class(&gtk::STYLE_CLASS_SUGGESTED_ACTION);
+        open_button.connect_clicked(clone!(window, url_input, status_label => move |_button| {
+            SignInWindow::open_browser(&window, &url_input, &status_label);
+        }));
+        grid.attach(&open_button, 1, 3, 2, 1);
+
+        let retry_button = gtk::Button::new_with_label("Try again");
+        retry_button
+            .get_style_context()
+            .add_class(&gtk::STYLE_CLASS_SUGGESTED_ACTION);
+        retry_button.connect_clicked(clone!(to_app => move |button| {
+            button.set_sensitive(false);
+            to_app.send(app::Signal::SignIn)
+                .expect("Sending message to application thread");
+        }));
+        /* takes the place of the open button after a failure */
+        grid.attach(&retry_button, 1, 3, 2, 1);
+
+        grid.attach(
+            &gtk::Separator::new(gtk::Orientation::Horizontal),
+            0,
+            4,
+            3,
+            1,
+        );
+
+        /* for when no browser can reach this computer, like on remote desktops */
+        let token_label = gtk::Label::new(None);
+        token_label.set_markup(
+            "Or use a personal access token, created at \
+             <a href=\"https://id.getharvest.com/developers\">id.getharvest.com/developers</a>.",
+        );
+        token_label.set_line_wrap(true);
+        token_label.set_xalign(0.0);
+        token_label.set_max_width_chars(50);
+        grid.attach(&token_label, 0, 5, 3, 1);
+
+        let token_input = gtk::Entry::new();
+        token_input.set_placeholder_text(Some("Token"));
+        token_input.set_visibility(false);
+        token_input.set_hexpand(true);
+        grid.attach(&token_input, 0, 6, 3, 1);
+
+        let account_input = gtk::Entry::new();
+        account_input.set_placeholder_text(Some("Account ID"));
+        account_input.set_input_purpose(gtk::InputPurpose::Digits);
+        grid.attach(&account_input, 0, 7, 1, 1);
+
+        let token_button = gtk::Button::new_with_label("Use token");
+        token_button.connect_clicked(
+            clone!(to_app, token_input, account_input, status_label => move |button| {
+                let token = token_input.get_text().unwrap().trim().to_string();
+                match account_input.get_text().unwrap().trim().parse() {
+                    Ok(account_id) if !token.is_empty() => {
+                        button.set_sensitive(false);
+                        status_label.set_text("Checking token...");
+                        to_app.send(app::Signal::UseAccessToken(token, account_id))
+                            .expect("Sending message to application thread");
+                    }
+                    _ => status_label.set_text("Enter both the token and the account ID."),
+                }
+            }),
+        );
+        grid.attach(&token_button, 1, 7, 2, 1);
+
+        grid.attach(&status_label, 0, 8, 3, 1);
+
+        let cancel_button = gtk::Button::new_with_label("Cancel");
+        cancel_button.connect_clicked(clone!(window => move |_button| {
+            window.close();
+        }));
+        grid.attach(&cancel_button, 0, 9, 1, 1);
+
+        window.show_all();
+        retry_button.hide();
+
+        SignInWindow {
+            window: window,
+            url_input: url_input,
+            status_label: status_label,
+            open_button: open_button,
+            retry_button: retry_button,
+            token_button: token_button,
+        }
+    }
+
+    /* a new attempt started, its url has a different state than the previous one */
+    pub fn wait_for(&self, url: &str) {
+        self.url_input.set_text(url);
+        self.status_label.set_text("");
+        self.retry_button.hide();
+        self.open_button.show();
+        self.open_button.set_sensitive(true);
+        self.token_button.set_sensitive(true);
+    }
+
+    pub fn failed(&self, message: &str) {
+        self.status_label.set_text(message);
+        self.open_button.hide();
+        self.retry_button.show();
+        self.retry_button.set_sensitive(true);
+        self.retry_button.grab_focus();
+        self.token_button.set_sensitive(true);
+    }
+
+    /* signing in using the browser is still possible */
+    pub fn token_failed(&self, message: &str) {
+        self.status_label.set_text(message);
+        self.token_button.set_sensitive(true);
+    }
+
+    pub fn close(&self) {
+        self.window.destroy();
+    }
+
+    fn open_browser(window: &gtk::Window, url_input: &gtk::Entry, status_label: &gtk::Label) {
+        let url = url_input.get_text().unwrap();
+        if url.is_empty() {
+            return;
+        }
+
+        match gtk::show_uri_on_window(Some(window), &url, gtk::get_current_event_time()) {
+            Ok(()) => status_label.set_text("Waiting for authorization in the web browser..."),
+            Err(e) => status_label.set_text(&format!(
+                "Unable to open a web browser ({}), copy the address above into one instead.",
+                e
+            )),
+        }
+    }
+}
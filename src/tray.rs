@@ -0,0 +1,152 @@
+use crate::app;
+use crate::ui;
+use ksni::menu::StandardItem;
+use ksni::{MenuItem, ToolTip};
+use std::sync::mpsc;
+use timer_for_harvest::*;
+
+/* a StatusNotifierItem, shown by KDE, most other desktops and GNOME with the AppIndicator extension */
+pub struct Tray {
+    to_app: mpsc::Sender<app::Signal>,
+    to_ui: glib::Sender<ui::Signal>,
+    /* those of today, to restart or stop one from the menu */
+    time_entries: Vec<TimeEntry>,
+}
+
+impl Tray {
+    pub fn start(
+        to_app: mpsc::Sender<app::Signal>,
+        to_ui: glib::Sender<ui::Signal>,
+    ) -> ksni::Handle<Tray> {
+        let service = ksni::TrayService::new(Tray {
+            to_app: to_app,
+            to_ui: to_ui,
+            time_entries: vec![],
+        });
+        let handle = service.handle();
+        service.spawn();
+        handle
+    }
+
+    pub fn set_time_entries(&mut self, time_entries: Vec<TimeEntry>) {
+        self.time_entries = time_entries;
+    }
+
+    fn running(&self) -> Option<&TimeEntry> {
+        self.time_entries.iter().find(|t| t.is_running)
+    }
+
+    fn describe(time_entry: &TimeEntry) -> String {
+        format!(
+            "{} {} - {}",
+            f32_to_duration_str(time_entry.hours),
+            time_entry.project.name_and_code(),
+            time_entry.task.name
+        )
+    }
+
+    /* an underscore would underline the next character instead */
+    fn label(text: String) -> String {
+        text.replace("_", "__")
+    }
+}
+
+impl ksni::Tray for Tray {
+    fn id(&self) -> String {
+        "timer-for-harvest".to_string()
+    }
+
+    fn title(&self) -> String {
+        match self.running() {
+            Some(time_entry) => Tray::describe(time_entry),
+            None => "Timer for Harvest".to_string(),
+        }
+    }
+
+    fn icon_name(&self) -> String {
+        match self.running() {
+            Some(_) => "media-playback-start-symbolic".to_string(),
+            None => "media-playback-pause-symbolic".to_string(),
+        }
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        let (title, description) = match self.running() {
+            Some(time_entry) => (
+                Tray::describe(time_entry),
+                time_entry.notes.clone().unwrap_or_default(),
+            ),
+            None => ("No timer is running".to_string(), "".to_string()),
+        };
+        ToolTip {
+            title: title,
+            description: description,
+            ..Default::default()
+        }
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.to_ui
+            .send(ui::Signal::ShowWindow)
+            .expect("Sending message to ui thread");
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        let mut items: Vec<MenuItem<Self>> = vec![];
+
+        for time_entry in &self.time_entries {
+            let id = time_entry.id;
+            let (icon_name, signal): (&str, fn(u32) -> app::Signal) = if time_entry.is_running {
+                ("media-playback-stop-symbolic", app::Signal::StopTimeEntry)
+            } else {
+                (
+                    "media-playback-start-symbolic",
+                    app::Signal::RestartTimeEntry,
+                )
+            };
+            items.push(
+                StandardItem {
+                    label: Tray::label(Tray::describe(time_entry)),
+                    icon_name: icon_name.to_string(),
+                    activate: Box::new(move |tray: &mut Tray| {
+                        tray.to_app
+                            .send(signal(id))
+                            .expect("Sending message to application thread");
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        if !self.time_entries.is_empty() {
+            items.push(MenuItem::Separator);
+        }
+
+        items.push(
+            StandardItem {
+                label: "Show window".to_string(),
+                activate: Box::new(|tray: &mut Tray| {
+                    tray.to_ui
+                        .send(ui::Signal::ShowWindow)
+                        .expect("Sending message to ui thread");
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+        items.push(
+            StandardItem {
+                label: "Quit".to_string(),
+                icon_name: "application-exit-symbolic".to_string(),
+                activate: Box::new(|tray: &mut Tray| {
+                    tray.to_ui
+                        .send(ui::Signal::Quit)
+                        .expect("Sending message to ui thread");
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+        items
+    }
+}
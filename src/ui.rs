@@ -1,11 +1,15 @@
 use crate::app;
 use crate::popup::Popup;
+use crate::sign_in::SignInWindow;
 use gio::prelude::*;
 use gtk::prelude::*;
+use std::cell::RefCell;
 use std::env;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
+use timer_for_harvest::week::Week;
 use timer_for_harvest::*;
 
 /* handy gtk callback clone macro taken from https://gtk-rs.org/docs-src/tutorial/closures */
@@ -26,33 +30,96 @@ macro_rules! clone {
     );
 }
 
+/* a link in a notice that asks to sign in again instead of opening a browser */
+pub const SIGN_IN_LINK: &str = "timer-for-harvest:sign-in";
+
 pub enum Signal {
     SetTitle(String),
-    SetTimeEntries(Vec<TimeEntry>),
+    /* the ids of the time entries with changes not saved in Harvest yet */
+    SetTimeEntries(Vec<TimeEntry>, Vec<u32>),
+    SetWeek(Week),
     OpenPopup(Vec<ProjectAssignment>),
     OpenPopupWithTimeEntry(Vec<ProjectAssignment>, TimeEntry),
+    /* the edited time entry and the one in Harvest */
+    ShowConflict(Vec<ProjectAssignment>, TimeEntry, TimeEntry),
+    /* for a new time entry with the hours filled in */
+    OpenPopupWithHours(Vec<ProjectAssignment>, f32),
+    /* the running time entry, the hours nobody was there and since when */
+    ShowIdle(TimeEntry, f32, String),
     TaskAssignments(Vec<TaskAssignment>),
     ShowNotice(String),
+    ShowLogin(String),
+    LoginFailed(String),
+    AccessTokenFailed(String),
+    HideLogin,
+    /* from the tray, the main window might be hidden */
+    ShowWindow,
+    Quit,
+    SetAccounts(Vec<Account>, u32),
 }
 
 pub struct Ui {
     application: gtk::Application,
     header_bar: gtk::HeaderBar,
+    account_chooser: gtk::ComboBoxText,
+    week_button: gtk::ToggleButton,
+    /* blocked while showing what the application sent */
+    week_toggled: glib::SignalHandlerId,
     grid: gtk::Grid,
-    total_amount_label: gtk::Label,
     no_time_entries_label: gtk::Label,
     to_app: mpsc::Sender<app::Signal>,
     popup: Option<Popup>,
+    sign_in: Option<SignInWindow>,
+    clock: Rc<RefCell<Clock>>,
+}
+
+/* counts running timers along every second, in between time entries sent by the application */
+struct Clock {
+    header_bar: gtk::HeaderBar,
+    total_amount_label: gtk::Label,
+    /* with the label showing their hours */
+    running: Vec<(TimeEntry, gtk::Label)>,
+    stopped_hours: f32,
+    /* TFH_SHOW_SECONDS */
+    show_seconds: bool,
 }
 
 impl Ui {
-    pub fn new(to_app: mpsc::Sender<app::Signal>) -> Ui {
-        let application = gtk::Application::new(
+    pub fn application() -> gtk::Application {
+        gtk::Application::new(
             Some("nl.frankgroeneveld.timer-for-harvest"),
             Default::default(),
         )
-        .unwrap();
+        .unwrap()
+    }
+
+    /* the instance already running shows its window or does the action instead */
+    pub fn hand_over_to_running_instance(
+        application: &gtk::Application,
+        action: &Option<String>,
+    ) -> bool {
+        if application.register(gio::NONE_CANCELLABLE).is_err() || !application.get_is_remote() {
+            return false;
+        }
+
+        match action {
+            Some(action) => application.activate_action(action, None),
+            None => application.activate(),
+        }
+        if let Some(connection) = application.get_dbus_connection() {
+            let _ = connection.flush_sync(gio::NONE_CANCELLABLE);
+        }
+        true
+    }
+
+    pub fn new(application: gtk::Application, to_app: mpsc::Sender<app::Signal>) -> Ui {
         let header_bar = gtk::HeaderBar::new();
+        let account_chooser = gtk::ComboBoxText::new();
+        let week_button = gtk::ToggleButton::new();
+        let week_toggled = week_button.connect_toggled(clone!(to_app => move |week_button| {
+            to_app.send(app::Signal::SetWeekView(week_button.get_active()))
+                .expect("Sending message to application thread");
+        }));
 
         let grid = gtk::Grid::new();
         grid.set_column_spacing(12);
@@ -83,16 +150,47 @@ impl Ui {
             1,
         );
 
-        application.connect_activate(clone!(to_app, header_bar, grid => move |app| {
-            gtk::timeout_add_seconds(60, clone!(to_app => move || {
-                to_app.send(app::Signal::MinutePassed)
-                    .expect("Sending message to application thread");
-                glib::Continue(true)
-            }));
-
-            Ui::main_window(app, &to_app, &header_bar, &grid, &total_grid);
+        let clock = Rc::new(RefCell::new(Clock {
+            header_bar: header_bar.clone(),
+            total_amount_label: total_amount_label,
+            running: vec![],
+            stopped_hours: 0.0,
+            show_seconds: env::var("TFH_SHOW_SECONDS").is_ok(),
         }));
 
+        Ui::add_actions(&application, &to_app);
+        Ui::watch_network(&to_app);
+
+        application.connect_activate(
+            clone!(to_app, header_bar, account_chooser, week_button, grid, clock => move |app| {
+                /* hidden when closed while keeping running */
+                if let Some(window) = app.get_active_window() {
+                    window.present();
+                    return;
+                }
+
+                gtk::timeout_add_seconds(60, clone!(to_app => move || {
+                    to_app.send(app::Signal::MinutePassed)
+                        .expect("Sending message to application thread");
+                    glib::Continue(true)
+                }));
+                gtk::timeout_add_seconds(1, clone!(clock => move || {
+                    clock.borrow().tick();
+                    glib::Continue(true)
+                }));
+
+                Ui::main_window(
+                    app,
+                    &to_app,
+                    &header_bar,
+                    &account_chooser,
+                    &week_button,
+                    &grid,
+                    &total_grid,
+                );
+            }),
+        );
+
         to_app
             .send(app::Signal::CheckVersion)
             .expect("Sending message to application thread");
@@ -100,37 +198,61 @@ impl Ui {
         Ui {
             application: application,
             header_bar: header_bar,
+            account_chooser: account_chooser,
+            week_button: week_button,
+            week_toggled: week_toggled,
             grid: grid,
-            total_amount_label: total_amount_label,
             no_time_entries_label: no_time_entries_label,
             to_app: to_app,
             popup: None,
+            sign_in: None,
+            clock: clock,
         }
     }
 
-    pub fn handle_app_signals(mut ui: Ui, from_app: glib::Receiver<Signal>) {
+    /* action is one of those added in add_actions, activated once running */
+    pub fn handle_app_signals(
+        mut ui: Ui,
+        from_app: glib::Receiver<Signal>,
+        action: Option<String>,
+    ) {
         let application = ui.application.clone();
         from_app.attach(None, move |signal| {
             match signal {
                 Signal::SetTitle(value) => {
                     ui.header_bar.set_title(Some(&value));
                 }
-                Signal::SetTimeEntries(time_entries) => {
-                    ui.set_time_entries(time_entries);
+                Signal::SetTimeEntries(time_entries, pending) => {
+                    ui.set_time_entries(time_entries, pending);
+                }
+                Signal::SetWeek(week) => {
+                    ui.set_week(week);
                 }
                 Signal::OpenPopup(project_assignments) => {
                     ui.open_popup(project_assignments, vec![], None);
                 }
                 Signal::OpenPopupWithTimeEntry(project_assignments, time_entry) => {
-                    let mut task_assignments = vec![];
-                    for project_assignment in &project_assignments {
-                        if project_assignment.project.id == time_entry.project.id {
-                            task_assignments = project_assignment.task_assignments.clone();
-                            break;
-                        }
-                    }
+                    let task_assignments =
+                        Ui::task_assignments_for(&project_assignments, time_entry.project.id);
                     ui.open_popup(project_assignments, task_assignments, Some(time_entry));
                 }
+                Signal::ShowConflict(project_assignments, mine, theirs) => {
+                    let task_assignments =
+                        Ui::task_assignments_for(&project_assignments, mine.project.id);
+                    ui.open_popup(project_assignments, task_assignments, Some(mine));
+                    if let Some(popup) = &ui.popup {
+                        popup.show_conflict(&theirs);
+                    }
+                }
+                Signal::OpenPopupWithHours(project_assignments, hours) => {
+                    ui.open_popup(project_assignments, vec![], None);
+                    if let Some(popup) = &ui.popup {
+                        popup.set_hours(hours);
+                    }
+                }
+                Signal::ShowIdle(time_entry, hours, since) => {
+                    ui.show_idle(time_entry, hours, &since);
+                }
                 Signal::TaskAssignments(task_assignments) => match &ui.popup {
                     Some(popup) => {
                         popup.load_tasks(task_assignments);
@@ -138,10 +260,32 @@ impl Ui {
                     None => {}
                 },
                 Signal::ShowNotice(message) => {
+                    /* only show the latest notice, they would overlap otherwise */
+                    for child in ui.grid.get_children() {
+                        if child.is::<gtk::InfoBar>() {
+                            ui.grid.remove(&child);
+                        }
+                    }
+
                     let bar = gtk::InfoBar::new();
+                    bar.set_show_close_button(true);
+                    bar.connect_response(|bar, _response| {
+                        bar.destroy();
+                    });
                     let content_area = bar.get_content_area().unwrap();
                     let label = gtk::Label::new(None);
                     label.set_markup(&message);
+                    let to_app = ui.to_app.clone();
+                    label.connect_activate_link(clone!(bar => move |_label, uri| {
+                        if uri == SIGN_IN_LINK {
+                            bar.destroy();
+                            to_app.send(app::Signal::SignIn)
+                                .expect("Sending message to application thread");
+                            Inhibit(true)
+                        } else {
+                            Inhibit(false)
+                        }
+                    }));
 
                     content_area
                         .downcast::<gtk::Container>()
@@ -150,16 +294,92 @@ impl Ui {
                     bar.show_all();
                     ui.grid.attach(&bar, 0, 0, 4, 1);
                 }
+                Signal::ShowLogin(url) => {
+                    ui.sign_in_window().wait_for(&url);
+                }
+                Signal::LoginFailed(message) => {
+                    ui.sign_in_window().failed(&message);
+                }
+                Signal::AccessTokenFailed(message) => {
+                    ui.sign_in_window().token_failed(&message);
+                }
+                Signal::HideLogin => {
+                    if let Some(sign_in) = ui.sign_in.take() {
+                        sign_in.close();
+                    }
+                }
+                Signal::ShowWindow => {
+                    Ui::show_window(&ui.application);
+                }
+                Signal::Quit => {
+                    ui.application.quit();
+                }
+                Signal::SetAccounts(accounts, active_id) => {
+                    ui.set_accounts(accounts, active_id);
+                }
             }
             glib::Continue(true)
         });
+
+        if let Some(action) = action {
+            glib::idle_add(clone!(application => move || {
+                application.activate_action(&action, None);
+                glib::Continue(false)
+            }));
+        }
         application.run(&[]);
     }
 
+    /* also offered by the desktop file and available to gapplication action */
+    fn add_actions(application: &gtk::Application, to_app: &mpsc::Sender<app::Signal>) {
+        let actions: Vec<(&str, bool, fn() -> app::Signal)> = vec![
+            ("new-entry", true, || app::Signal::NewTimeEntry),
+            ("stop-running", false, || app::Signal::StopRunningTimers),
+            ("restart-last", false, || app::Signal::RestartLastTimer),
+            ("today", true, || app::Signal::TodayDate),
+        ];
+
+        for (name, shows_window, signal) in actions {
+            let action = gio::SimpleAction::new(name, None);
+            action.connect_activate(clone!(application, to_app => move |_action, _parameter| {
+                if shows_window {
+                    Ui::show_window(&application);
+                }
+                to_app.send(signal())
+                    .expect("Sending message to application thread");
+            }));
+            application.add_action(&action);
+        }
+    }
+
+    /* time entries are retrieved once the network is up again, after resuming for example */
+    fn watch_network(to_app: &mpsc::Sender<app::Signal>) {
+        let monitor = match gio::NetworkMonitor::get_default() {
+            Some(monitor) => monitor,
+            None => return,
+        };
+        to_app
+            .send(app::Signal::NetworkChanged(monitor.get_network_available()))
+            .expect("Sending message to application thread");
+        monitor.connect_network_changed(clone!(to_app => move |_monitor, available| {
+            to_app.send(app::Signal::NetworkChanged(available))
+                .expect("Sending message to application thread");
+        }));
+    }
+
+    fn show_window(application: &gtk::Application) {
+        match application.get_active_window() {
+            Some(window) => window.present(),
+            None => application.activate(),
+        }
+    }
+
     pub fn main_window(
         application: &gtk::Application,
         to_app: &mpsc::Sender<app::Signal>,
         header_bar: &gtk::HeaderBar,
+        account_chooser: &gtk::ComboBoxText,
+        week_button: &gtk::ToggleButton,
         grid: &gtk::Grid,
         total_grid: &gtk::Grid,
     ) -> gtk::ApplicationWindow {
@@ -184,6 +404,14 @@ impl Ui {
         window.set_default_size(a_w, a_h);
         window.set_size_request(a_w, a_h);
 
+        /* the tray icon shows it again */
+        if env::var("TFH_KEEP_RUNNING").is_ok() {
+            window.connect_delete_event(|window, _event| {
+                window.hide();
+                Inhibit(true)
+            });
+        }
+
         window.add_events(gdk::EventMask::KEY_PRESS_MASK);
         window.connect_key_press_event(clone!(to_app => move |_window, event| {
             if event.get_keyval() == gdk::enums::key::F5 {
@@ -242,6 +470,54 @@ impl Ui {
                 .expect("Sending message to application thread");
         }));
 
+        week_button.set_image(Some(&gtk::Image::new_from_icon_name(
+            Some("x-office-calendar-symbolic"),
+            gtk::IconSize::Button,
+        )));
+        week_button.set_tooltip_text(Some("Show the whole week"));
+        header_bar.pack_start(week_button);
+
+        let sign_out_action = gio::SimpleAction::new("sign-out", None);
+        sign_out_action.connect_activate(clone!(to_app, window => move |_action, _parameter| {
+            let confirmation_box = gtk::MessageDialog::new(
+                Some(&window),
+                gtk::DialogFlags::MODAL,
+                gtk::MessageType::Question,
+                gtk::ButtonsType::YesNo,
+                "Are you sure you want to sign out of Harvest?",
+            );
+
+            let confirmation_response = confirmation_box.run();
+            confirmation_box.destroy();
+
+            if confirmation_response == gtk::ResponseType::Yes {
+                to_app.send(app::Signal::SignOut)
+                    .expect("Sending message to application thread");
+            }
+        }));
+        application.add_action(&sign_out_action);
+
+        let menu = gio::Menu::new();
+        menu.append(Some("Sign out"), Some("app.sign-out"));
+        let menu_button = gtk::MenuButton::new();
+        menu_button.set_image(Some(&gtk::Image::new_from_icon_name(
+            Some("open-menu-symbolic"),
+            gtk::IconSize::Button,
+        )));
+        menu_button.set_menu_model(Some(&menu));
+        header_bar.pack_end(&menu_button);
+
+        /* only shown when signed in to more than one account, see set_accounts */
+        account_chooser.set_no_show_all(true);
+        account_chooser.set_valign(gtk::Align::Center);
+        header_bar.pack_end(account_chooser);
+        account_chooser.connect_changed(clone!(to_app => move |account_chooser| {
+            if let Some(id) = account_chooser.get_active_id() {
+                to_app.send(app::Signal::SwitchAccount(id.parse().unwrap()))
+                    .expect("Sending message to application thread");
+            }
+        }));
+
         let scroll_view = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
         scroll_view.set_min_content_height(400);
         scroll_view.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Always);
@@ -274,14 +550,22 @@ impl Ui {
         window
     }
 
-    pub fn set_total(&self, total_hours: f32) {
-        let formatted_label = format!("<b>{}</b>", f32_to_duration_str(total_hours));
-        self.total_amount_label.set_label(&formatted_label);
+    pub fn set_accounts(&self, accounts: Vec<Account>, active_id: u32) {
+        self.account_chooser.remove_all();
+        for account in &accounts {
+            self.account_chooser
+                .append(Some(&account.id.to_string()), &account.name);
+        }
+        self.account_chooser
+            .set_active_id(Some(&active_id.to_string()));
+        self.account_chooser.set_visible(accounts.len() > 1);
     }
 
-    pub fn set_time_entries(&mut self, time_entries: Vec<TimeEntry>) {
+    pub fn set_time_entries(&mut self, time_entries: Vec<TimeEntry>, pending: Vec<u32>) {
+        self.show_week_view(false);
         let total_entries = time_entries.len() as i32;
-        let mut total_hours = 0.0;
+        let mut running = vec![];
+        let mut stopped_hours = 0.0;
         let mut row_number = total_entries + 1; /* info bar is row 0 */
 
         for child in self.grid.get_children() {
@@ -291,8 +575,6 @@ impl Ui {
         }
 
         for time_entry in time_entries {
-            total_hours += time_entry.hours;
-
             let notes = match time_entry.notes.as_ref() {
                 Some(n) => format_timeentry_notes_for_list(n, None),
                 None => "".to_string(),
@@ -314,7 +596,24 @@ impl Ui {
 
             let hours_label = gtk::Label::new(Some(&f32_to_duration_str(time_entry.hours)));
             hours_label.set_xalign(0.0);
-            self.grid.attach(&hours_label, 1, row_number, 1, 1);
+            if time_entry.is_running {
+                running.push((time_entry.clone(), hours_label.clone()));
+            } else {
+                stopped_hours += time_entry.hours;
+            }
+            if pending.contains(&time_entry.id) {
+                let hours_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+                hours_box.pack_start(&hours_label, false, false, 0);
+                let pending_image = gtk::Image::new_from_icon_name(
+                    Some("emblem-synchronizing-symbolic"),
+                    gtk::IconSize::Button,
+                );
+                pending_image.set_tooltip_text(Some("Not saved in Harvest yet"));
+                hours_box.pack_start(&pending_image, false, false, 0);
+                self.grid.attach(&hours_box, 1, row_number, 1, 1);
+            } else {
+                self.grid.attach(&hours_label, 1, row_number, 1, 1);
+            }
 
             let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 2);
             hbox.set_spacing(0);
@@ -376,7 +675,111 @@ impl Ui {
             self.grid.attach(&self.no_time_entries_label, 0, 0, 1, 1)
         }
 
-        self.set_total(total_hours);
+        {
+            let mut clock = self.clock.borrow_mut();
+            clock.running = running;
+            clock.stopped_hours = stopped_hours;
+            clock.tick();
+        }
+
+        self.grid.show_all();
+    }
+
+    fn show_week_view(&self, active: bool) {
+        self.week_button.block_signal(&self.week_toggled);
+        self.week_button.set_active(active);
+        self.week_button.unblock_signal(&self.week_toggled);
+    }
+
+    /* a row per project with its hours on each day, a day shows its time entries when clicked */
+    pub fn set_week(&mut self, week: Week) {
+        self.show_week_view(true);
+        for child in self.grid.get_children() {
+            if !child.is::<gtk::InfoBar>() {
+                self.grid.remove(&child);
+            }
+        }
+        let hours_label = |hours: f32, bold: bool| {
+            let text = if hours > 0.0 {
+                f32_to_duration_str(hours)
+            } else {
+                "".to_string()
+            };
+            let label = gtk::Label::new(None);
+            label.set_use_markup(true);
+            if bold {
+                label.set_markup(&format!("<b>{}</b>", text));
+            } else {
+                label.set_label(&text);
+            }
+            label.set_xalign(1.0);
+            label
+        };
+        let days = week.days.len() as i32;
+        let total_row = week.rows.len() as i32 + 2; /* info bar is row 0 */
+
+        for (column, day) in week.days.iter().enumerate() {
+            let button = gtk::Button::new_with_label(&day.format("%a\n%-d").to_string());
+            button.set_relief(gtk::ReliefStyle::None);
+            let to_app = self.to_app.clone();
+            let day = *day;
+            button.connect_clicked(move |_button| {
+                to_app
+                    .send(app::Signal::ShowDay(day))
+                    .expect("Sending message to application thread");
+            });
+            self.grid.attach(&button, column as i32 + 1, 1, 1, 1);
+
+            let total = hours_label(week.day_total(column), true);
+            self.grid.attach(&total, column as i32 + 1, total_row, 1, 1);
+        }
+
+        for (index, row) in week.rows.iter().enumerate() {
+            let row_number = index as i32 + 2;
+            let project = format!(
+                "<b>{}</b>\n{}",
+                escape_html(&row.project.name_and_code()),
+                escape_html(&row.client.name)
+            );
+            let project_label = gtk::Label::new(Some(&project));
+            project_label.set_xalign(0.0);
+            project_label.set_line_wrap(true);
+            project_label.set_use_markup(true);
+            project_label.set_hexpand(true);
+            self.grid.attach(&project_label, 0, row_number, 1, 1);
+
+            for (column, hours) in row.hours.iter().enumerate() {
+                let label = hours_label(*hours, false);
+                self.grid
+                    .attach(&label, column as i32 + 1, row_number, 1, 1);
+            }
+            let total = row.hours.iter().fold(0.0, |total, hours| total + hours);
+            /* in bold while its timer is running */
+            let total_label = hours_label(total, row.is_running);
+            self.grid.attach(&total_label, days + 1, row_number, 1, 1);
+        }
+
+        if week.rows.is_empty() {
+            self.grid
+                .attach(&self.no_time_entries_label, 0, 2, days + 2, 1);
+        }
+        let total_label = gtk::Label::new(Some("<b>Total</b>"));
+        total_label.set_use_markup(true);
+        total_label.set_xalign(0.0);
+        self.grid.attach(&total_label, 0, total_row, 1, 1);
+        let week_label = gtk::Label::new(Some("Week"));
+        week_label.set_xalign(1.0);
+        self.grid.attach(&week_label, days + 1, 1, 1, 1);
+        self.grid
+            .attach(&hours_label(week.total(), true), days + 1, total_row, 1, 1);
+
+        /* the hours of a running timer are updated every minute here */
+        {
+            let mut clock = self.clock.borrow_mut();
+            clock.running = vec![];
+            clock.stopped_hours = week.total();
+            clock.tick();
+        }
 
         self.grid.show_all();
     }
@@ -399,4 +802,97 @@ impl Ui {
 
         self.popup = Some(popup);
     }
+
+    fn show_idle(&self, time_entry: TimeEntry, hours: f32, since: &str) {
+        let dialog = gtk::MessageDialog::new(
+            self.application.get_active_window().as_ref(),
+            gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!(
+                "You were away for {} since {}",
+                f32_to_duration_str(hours),
+                since
+            ),
+        );
+        dialog.set_property_secondary_text(Some(&format!(
+            "The timer of {} - {} kept running. What should happen with this time?",
+            time_entry.project.name_and_code(),
+            time_entry.task.name
+        )));
+        dialog.add_button("Book on other project", gtk::ResponseType::Apply);
+        dialog.add_button("Discard", gtk::ResponseType::Reject);
+        dialog.add_button("Keep", gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let to_app = self.to_app.clone();
+        dialog.connect_response(move |dialog, response| {
+            let choice = match response {
+                gtk::ResponseType::Apply => app::IdleChoice::Book,
+                gtk::ResponseType::Reject => app::IdleChoice::Discard,
+                _ => app::IdleChoice::Keep,
+            };
+            to_app
+                .send(app::Signal::ResolveIdle(time_entry.id, hours, choice))
+                .expect("Sending message to application thread");
+            dialog.destroy();
+        });
+        dialog.show_all();
+    }
+
+    fn task_assignments_for(
+        project_assignments: &[ProjectAssignment],
+        project_id: u32,
+    ) -> Vec<TaskAssignment> {
+        for project_assignment in project_assignments {
+            if project_assignment.project.id == project_id {
+                return project_assignment.task_assignments.clone();
+            }
+        }
+        vec![]
+    }
+
+    fn sign_in_window(&mut self) -> &SignInWindow {
+        if self.sign_in.is_none() {
+            self.sign_in = Some(SignInWindow::new(&self.application, self.to_app.clone()));
+        }
+        self.sign_in.as_ref().unwrap()
+    }
+}
+
+impl Clock {
+    fn tick(&self) {
+        let now = chrono::Utc::now();
+        let mut total_hours = self.stopped_hours;
+        for (time_entry, label) in &self.running {
+            let hours = time_entry.hours_at(now);
+            label.set_label(&self.format(hours));
+            total_hours += hours;
+        }
+
+        let total = if self.running.is_empty() {
+            f32_to_duration_str(total_hours)
+        } else {
+            self.format(total_hours)
+        };
+        self.total_amount_label
+            .set_label(&format!("<b>{}</b>", total));
+
+        let subtitle = self.running.first().map(|(time_entry, _label)| {
+            format!(
+                "{} {}",
+                self.format(time_entry.hours_at(now)),
+                time_entry.project.name_and_code()
+            )
+        });
+        self.header_bar.set_subtitle(subtitle.as_deref());
+    }
+
+    fn format(&self, hours: f32) -> String {
+        if self.show_seconds {
+            f32_to_duration_with_seconds_str(hours)
+        } else {
+            f32_to_duration_str(hours)
+        }
+    }
 }
@@ -0,0 +1,78 @@
+use crate::{Client, Project, TimeEntry};
+use chrono::{Datelike, Duration, NaiveDate};
+
+/* the hours of a week per project, like the timesheet in Harvest */
+#[derive(Clone)]
+pub struct Week {
+    /* Monday to Sunday */
+    pub days: Vec<NaiveDate>,
+    pub rows: Vec<WeekRow>,
+}
+
+#[derive(Clone)]
+pub struct WeekRow {
+    pub project: Project,
+    pub client: Client,
+    /* one for each of the days */
+    pub hours: Vec<f32>,
+    pub is_running: bool,
+}
+
+impl Week {
+    /* the week date is in */
+    pub fn days_of(date: NaiveDate) -> Vec<NaiveDate> {
+        let monday = date - Duration::days(date.weekday().num_days_from_monday().into());
+        (0..7).map(|day| monday + Duration::days(day)).collect()
+    }
+
+    /* time entries of other weeks are left out */
+    pub fn new(date: NaiveDate, time_entries: &[TimeEntry]) -> Week {
+        let days = Week::days_of(date);
+        let mut rows: Vec<WeekRow> = vec![];
+
+        for time_entry in time_entries {
+            let day = match days
+                .iter()
+                .position(|d| d.to_string() == time_entry.spent_date)
+            {
+                Some(day) => day,
+                None => continue,
+            };
+            let index = match rows
+                .iter()
+                .position(|r| r.project.id == time_entry.project.id)
+            {
+                Some(index) => index,
+                None => {
+                    rows.push(WeekRow {
+                        project: time_entry.project.clone(),
+                        client: time_entry.client.clone(),
+                        hours: vec![0.0; days.len()],
+                        is_running: false,
+                    });
+                    rows.len() - 1
+                }
+            };
+            rows[index].hours[day] += time_entry.hours;
+            rows[index].is_running |= time_entry.is_running;
+        }
+        rows.sort_by(|a, b| {
+            (&a.client.name, &a.project.name).cmp(&(&b.client.name, &b.project.name))
+        });
+
+        Week {
+            days: days,
+            rows: rows,
+        }
+    }
+
+    pub fn day_total(&self, day: usize) -> f32 {
+        self.rows
+            .iter()
+            .fold(0.0, |total, row| total + row.hours[day])
+    }
+
+    pub fn total(&self) -> f32 {
+        (0..self.days.len()).fold(0.0, |total, day| total + self.day_total(day))
+    }
+}
@@ -0,0 +1,369 @@
+mod mock_server;
+
+#[cfg(test)]
+mod test {
+    use crate::mock_server::{
+        MockServer, ACCOUNT_ID, OTHER_ACCOUNT_ID, OTHER_USER_ID, TOKEN, USER_ID,
+    };
+    use std::time::{Duration, Instant};
+    use timer_for_harvest::{Authorization, Harvest, HarvestError};
+
+    fn today() -> chrono::NaiveDate {
+        chrono::NaiveDate::from_ymd(2022, 8, 1)
+    }
+
+    #[test]
+    fn should_retrieve_current_user() {
+        let server = MockServer::start();
+        let user = server.harvest().current_user().unwrap();
+
+        assert_eq!(USER_ID, user.id);
+    }
+
+    #[test]
+    fn should_switch_between_accounts() {
+        let server = MockServer::start();
+        let mut harvest = server.harvest();
+        assert_eq!(ACCOUNT_ID, harvest.account_id());
+
+        harvest.switch_account(OTHER_ACCOUNT_ID);
+        assert_eq!(OTHER_ACCOUNT_ID, harvest.account_id());
+        assert_eq!(OTHER_USER_ID, harvest.current_user().unwrap().id);
+
+        harvest.switch_account(999);
+        match harvest.current_user() {
+            Err(HarvestError::Status(403, _)) => {}
+            _ => panic!("expected an unknown account"),
+        }
+    }
+
+    #[test]
+    fn should_only_list_granted_harvest_accounts() {
+        let server = MockServer::start();
+        let harvest = Harvest::from_authorization(Authorization {
+            token: TOKEN.to_string(),
+            account_id: ACCOUNT_ID,
+            expires_at: u64::MAX,
+            account_ids: vec![ACCOUNT_ID, OTHER_ACCOUNT_ID],
+        })
+        .with_api_url(&server.url)
+        .with_id_url(&server.url.replace("/v2", ""));
+
+        let accounts = harvest.accounts().unwrap();
+        assert_eq!(2, accounts.len());
+        assert_eq!("Mock Company", accounts[0].name);
+        assert_eq!(OTHER_ACCOUNT_ID, accounts[1].id);
+
+        let accounts = server.harvest().accounts().unwrap();
+        assert_eq!(1, accounts.len());
+        assert_eq!(ACCOUNT_ID, accounts[0].id);
+    }
+
+    #[test]
+    fn should_retrieve_all_project_assignment_pages() {
+        let server = MockServer::start();
+        let project_assignments = server.harvest().active_project_assignments().unwrap();
+
+        assert_eq!(5, project_assignments.len());
+        assert_eq!(
+            "[P5] Project 5",
+            project_assignments[4].project.name_and_code()
+        );
+        assert_eq!(
+            3,
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.starts_with("GET /v2/users/me/project_assignments"))
+                .count()
+        );
+        assert!(server
+            .requests()
+            .contains(&"GET /v2/users/me/project_assignments?page=3".to_string()));
+    }
+
+    #[test]
+    fn should_retrieve_single_project_assignment_page() {
+        let server = MockServer::start();
+        server.set_project_assignment_count(1);
+        let project_assignments = server.harvest().active_project_assignments().unwrap();
+
+        assert_eq!(1, project_assignments.len());
+        assert_eq!(1, server.requests().len());
+    }
+
+    #[test]
+    fn should_start_and_stop_timer() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+
+        let started = harvest
+            .start_timer(1, 10, "Writing tests".to_string(), 0.0, &today())
+            .unwrap();
+        assert!(started.is_running);
+        assert_eq!(Some("Writing tests".to_string()), started.notes);
+        assert_eq!("2022-08-01", started.spent_date);
+
+        let stopped = harvest.stop_timer(started.id).unwrap();
+        assert!(!stopped.is_running);
+        assert_eq!(started.id, stopped.id);
+    }
+
+    #[test]
+    fn should_not_start_timer_with_hours() {
+        let server = MockServer::start();
+        let time_entry = server
+            .harvest()
+            .start_timer(2, 20, "".to_string(), 1.5, &today())
+            .unwrap();
+
+        assert!(!time_entry.is_running);
+        assert_eq!(1.5, time_entry.hours);
+        assert_eq!(None, time_entry.notes);
+    }
+
+    #[test]
+    fn should_restart_timer() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+
+        let first = harvest
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+            .unwrap();
+        harvest.stop_timer(first.id).unwrap();
+        let second = harvest
+            .start_timer(2, 20, "".to_string(), 0.0, &today())
+            .unwrap();
+
+        let restarted = harvest.restart_timer(first.id).unwrap();
+        assert!(restarted.is_running);
+
+        let time_entries = harvest
+            .time_entries_for(
+                &harvest.current_user().unwrap(),
+                "2022-08-01".to_string(),
+                "2022-08-01".to_string(),
+            )
+            .unwrap();
+        assert_eq!(2, time_entries.len());
+        for time_entry in time_entries {
+            assert_eq!(time_entry.id == first.id, time_entry.is_running);
+            assert_ne!(time_entry.id == second.id, time_entry.is_running);
+        }
+    }
+
+    #[test]
+    fn should_update_timer() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let started = harvest
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+            .unwrap();
+        harvest.stop_timer(started.id).unwrap();
+
+        let updated = harvest
+            .update_timer(
+                started.id,
+                3,
+                31,
+                "Changed".to_string(),
+                2.25,
+                false,
+                "2022-08-02".to_string(),
+            )
+            .unwrap();
+        assert_eq!(3, updated.project.id);
+        assert_eq!("Meetings", updated.task.name);
+        assert_eq!(Some("Changed".to_string()), updated.notes);
+        assert_eq!(2.25, updated.hours);
+        assert_eq!("2022-08-02", updated.spent_date);
+    }
+
+    #[test]
+    fn should_delete_timer() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let started = harvest
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+            .unwrap();
+
+        harvest.delete_timer(started.id).unwrap();
+        assert!(server.time_entries().is_empty());
+    }
+
+    #[test]
+    fn should_only_retrieve_time_entries_in_range() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        harvest
+            .start_timer(1, 10, "".to_string(), 1.0, &today())
+            .unwrap();
+        harvest
+            .start_timer(1, 10, "".to_string(), 1.0, &today().succ())
+            .unwrap();
+
+        let time_entries = harvest
+            .time_entries_for(
+                &harvest.current_user().unwrap(),
+                "2022-08-02".to_string(),
+                "2022-08-02".to_string(),
+            )
+            .unwrap();
+        assert_eq!(1, time_entries.len());
+        assert_eq!("2022-08-02", time_entries[0].spent_date);
+    }
+
+    #[test]
+    fn should_retrieve_all_time_entry_pages() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        for hours in 1..=5 {
+            harvest
+                .start_timer(1, 10, "".to_string(), hours as f32, &today())
+                .unwrap();
+        }
+
+        let time_entries = harvest
+            .time_entries_for(
+                &harvest.current_user().unwrap(),
+                "2022-08-01".to_string(),
+                "2022-08-01".to_string(),
+            )
+            .unwrap();
+        assert_eq!(5, time_entries.len());
+        assert_eq!(5.0, time_entries[4].hours);
+        assert!(server.requests().contains(
+            &"GET /v2/time_entries?from=2022-08-01&to=2022-08-01&user_id=1&page=3".to_string()
+        ));
+    }
+
+    #[test]
+    fn should_report_validation_errors() {
+        let server = MockServer::start();
+        let result = server
+            .harvest()
+            .start_timer(99, 10, "".to_string(), 0.0, &today());
+
+        match result {
+            Err(HarvestError::Status(422, message)) => {
+                assert_eq!("Project is not assigned to the current user", message)
+            }
+            _ => panic!("expected a validation error"),
+        }
+    }
+
+    #[test]
+    fn should_report_expired_authorization() {
+        let server = MockServer::start();
+        let harvest = Harvest::from_token("expired".to_string(), ACCOUNT_ID)
+            .with_api_url(&server.url)
+            .with_retry_policy(MockServer::retry_policy());
+
+        match harvest.current_user() {
+            Err(HarvestError::AuthExpired) => {}
+            _ => panic!("expected an expired authorization"),
+        }
+        /* retrying does not help, signing in again does */
+        assert_eq!(1, server.requests().len());
+    }
+
+    #[test]
+    fn should_retry_server_errors() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let started = harvest
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+            .unwrap();
+        server.fail_next(503, "<html>Service Unavailable</html>");
+
+        assert!(!harvest.stop_timer(started.id).unwrap().is_running);
+        assert_eq!(
+            2,
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.ends_with("/stop"))
+                .count()
+        );
+    }
+
+    #[test]
+    fn should_report_server_errors_after_retrying() {
+        let server = MockServer::start();
+        for _ in 0..3 {
+            server.fail_next(500, "<html>Internal Server Error</html>");
+        }
+
+        match server.harvest().current_user() {
+            Err(HarvestError::Status(500, _)) => {}
+            _ => panic!("expected a server error"),
+        }
+        assert_eq!(3, server.requests().len());
+    }
+
+    #[test]
+    fn should_not_retry_starting_timer_after_server_error() {
+        let server = MockServer::start();
+        server.fail_next(500, "<html>Internal Server Error</html>");
+
+        match server
+            .harvest()
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+        {
+            Err(HarvestError::Status(500, _)) => {}
+            _ => panic!("expected a server error"),
+        }
+        assert_eq!(vec!["POST /v2/time_entries".to_string()], server.requests());
+    }
+
+    #[test]
+    fn should_wait_for_rate_limit() {
+        let server = MockServer::start();
+        server.rate_limit_next(1);
+
+        let started_at = Instant::now();
+        let time_entry = server
+            .harvest()
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+            .unwrap();
+        assert!(started_at.elapsed() >= Duration::from_secs(1));
+        assert_eq!(1, server.time_entries().len());
+        assert_eq!(time_entry.id, server.time_entries()[0]["id"]);
+    }
+
+    #[test]
+    fn should_not_wait_for_long_rate_limit() {
+        let server = MockServer::start();
+        server.rate_limit_next(900);
+
+        match server.harvest().current_user() {
+            Err(HarvestError::Status(429, _)) => {}
+            _ => panic!("expected a rate limit error"),
+        }
+    }
+
+    #[test]
+    fn should_report_unexpected_structure() {
+        let server = MockServer::start();
+        server.fail_next(200, "{\"unexpected\": true}");
+
+        match server.harvest().current_user() {
+            Err(HarvestError::Deserialize(_, body)) => {
+                assert_eq!("{\"unexpected\": true}", body)
+            }
+            _ => panic!("expected a deserialization error"),
+        }
+    }
+
+    #[test]
+    fn should_report_unreachable_server() {
+        let harvest = Harvest::from_token("token".to_string(), ACCOUNT_ID)
+            .with_api_url("http://127.0.0.1:1/v2")
+            .with_retry_policy(MockServer::retry_policy());
+
+        match harvest.current_user() {
+            Err(HarvestError::Transport(_)) => {}
+            _ => panic!("expected a transport error"),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+mod mock_server;
+
+#[cfg(test)]
+mod test {
+    use crate::mock_server::MockServer;
+    use timer_for_harvest::cli::{Cli, CliError, Command, Output};
+
+    fn today() -> chrono::NaiveDate {
+        chrono::NaiveDate::from_ymd(2022, 8, 1)
+    }
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        Command::parse(&args)
+    }
+
+    fn run(server: &MockServer, args: &[&str]) -> Result<String, CliError> {
+        let cli = Cli::new(server.harvest(), today());
+        let mut out = vec![];
+        cli.run(&parse(args)?, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn should_parse_commands() {
+        assert_eq!(
+            Command::Start {
+                project: "p1".to_string(),
+                task: "dev".to_string(),
+                notes: "".to_string()
+            },
+            parse(&["start", "p1", "dev"]).unwrap()
+        );
+        assert_eq!(Command::Restart(12), parse(&["restart", "12"]).unwrap());
+        assert_eq!(
+            Command::List(Some(chrono::NaiveDate::from_ymd(2022, 7, 31)), Output::Text),
+            parse(&["list", "--date", "2022-07-31"]).unwrap()
+        );
+        assert_eq!(
+            Command::List(None, Output::Format("{duration}".to_string())),
+            parse(&["list", "--format", "{duration}"]).unwrap()
+        );
+        assert_eq!(
+            Command::Status(Output::Json),
+            parse(&["status", "--json"]).unwrap()
+        );
+        assert_eq!(
+            Command::Add {
+                project: "p1".to_string(),
+                task: "dev".to_string(),
+                hours: 1.5,
+                notes: "Notes".to_string()
+            },
+            parse(&["add", "p1", "dev", "1:30", "Notes"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_arguments() {
+        for args in &[
+            vec!["stop", "now"],
+            vec!["restart", "abc"],
+            vec!["list", "--date", "yesterday"],
+            vec!["add", "p1", "dev", "soon"],
+            vec!["start", "p1"],
+            vec!["status", "--date", "2022-07-31"],
+            vec!["status", "--json", "--format", "{id}"],
+            vec!["list", "--format"],
+        ] {
+            match parse(args) {
+                Err(CliError::Usage(_)) => {}
+                _ => panic!("expected a usage error for {:?}", args),
+            }
+        }
+    }
+
+    #[test]
+    fn should_start_and_stop_timer() {
+        let server = MockServer::start();
+
+        let output = run(&server, &["start", "project 2", "meet", "Planning"]).unwrap();
+        assert!(output.starts_with("Started "));
+        assert!(output.contains("[P2] Project 2 (Client 2) - Meetings - Planning (running)"));
+
+        let output = run(&server, &["status"]).unwrap();
+        assert!(output.contains("Project 2"));
+
+        let output = run(&server, &["stop"]).unwrap();
+        assert!(output.starts_with("Stopped "));
+        match run(&server, &["status"]) {
+            Err(CliError::NotRunning) => {}
+            _ => panic!("expected no running timer"),
+        }
+        match run(&server, &["stop"]) {
+            Err(CliError::NoMatch(_)) => {}
+            _ => panic!("expected no running timer"),
+        }
+    }
+
+    #[test]
+    fn should_match_projects_by_code() {
+        let server = MockServer::start();
+
+        run(&server, &["add", "P3", "Development", "0:45"]).unwrap();
+        let time_entries = server.time_entries();
+        assert_eq!(3, time_entries[0]["project"]["id"]);
+        assert_eq!(0.75, time_entries[0]["hours"]);
+        assert_eq!(false, time_entries[0]["is_running"]);
+    }
+
+    #[test]
+    fn should_report_ambiguous_and_unknown_projects() {
+        let server = MockServer::start();
+
+        match run(&server, &["start", "project", "development"]) {
+            Err(CliError::NoMatch(message)) => {
+                assert!(message.contains("[P1] Project 1 (Client 1)"))
+            }
+            _ => panic!("expected more than one match"),
+        }
+        match run(&server, &["start", "unknown", "development"]) {
+            Err(CliError::NoMatch(_)) => {}
+            _ => panic!("expected no match"),
+        }
+        assert!(server.time_entries().is_empty());
+    }
+
+    #[test]
+    fn should_list_restart_and_delete_time_entries() {
+        let server = MockServer::start();
+        run(
+            &server,
+            &["add", "P1", "dev", "1:00", "First\n\nSecond line"],
+        )
+        .unwrap();
+        run(&server, &["add", "P2", "dev", "0:30"]).unwrap();
+        let id = server.time_entries()[0]["id"].as_u64().unwrap().to_string();
+
+        let output = run(&server, &["list"]).unwrap();
+        assert!(output.contains(&format!(
+            "{} 1:00 [P1] Project 1 (Client 1) - Development - First - Second line\n",
+            id
+        )));
+        assert!(output.ends_with("Total 1:30\n"));
+        assert_eq!(
+            "Total 0:00\n",
+            run(&server, &["list", "--date", "2022-07-31"]).unwrap()
+        );
+
+        assert!(run(&server, &["restart", &id])
+            .unwrap()
+            .starts_with("Restarted"));
+        assert!(run(&server, &["delete", &id])
+            .unwrap()
+            .starts_with("Deleted"));
+        assert_eq!(1, server.time_entries().len());
+    }
+
+    #[test]
+    fn should_show_time_entries_as_json_or_template() {
+        let server = MockServer::start();
+        run(&server, &["start", "P2", "meet", "Planning\nReview"]).unwrap();
+
+        let output = run(&server, &["status", "--json"]).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!("Project 2", json[0]["project"]);
+        assert_eq!("Meetings", json[0]["task"]);
+        assert_eq!("Planning\nReview", json[0]["notes"]);
+        assert_eq!("0:00", json[0]["duration"]);
+        assert_eq!(true, json[0]["running"]);
+
+        let output = run(
+            &server,
+            &[
+                "status",
+                "--format",
+                "{code}/{task}: {notes} {duration} {unknown}",
+            ],
+        )
+        .unwrap();
+        assert_eq!("P2/Meetings: Planning - Review 0:00 {unknown}\n", output);
+
+        run(&server, &["stop"]).unwrap();
+        let cli = Cli::new(server.harvest(), today());
+        let mut out = vec![];
+        match cli.run(&Command::Status(Output::Json), &mut out) {
+            Err(CliError::NotRunning) => assert_eq!(b"[]\n".to_vec(), out),
+            _ => panic!("expected no running timer"),
+        }
+        assert_eq!(
+            "Meetings 0.00\n",
+            run(&server, &["list", "--format", "{task} {hours}"]).unwrap()
+        );
+    }
+
+    /* scripts depend on these, they should only change on purpose */
+    #[test]
+    fn should_keep_json_fields() {
+        let server = MockServer::start();
+        run(&server, &["start", "P1", "dev"]).unwrap();
+
+        let output = run(&server, &["list", "--json"]).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        let mut keys: Vec<&String> = json[0].as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(
+            vec![
+                "client", "code", "date", "duration", "hours", "id", "notes", "project", "running",
+                "task"
+            ],
+            keys
+        );
+    }
+}
@@ -0,0 +1,151 @@
+mod private_bus;
+
+#[cfg(test)]
+mod test {
+    use crate::private_bus::PrivateBus;
+    use dbus::blocking::Connection;
+    use dbus::Message;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+    use timer_for_harvest::idle::{Away, Backend, IdleMonitor, IdleWatch};
+    use timer_for_harvest::TimeEntry;
+
+    /* answers method calls of name like the desktop would, with milliseconds idle */
+    fn fake_monitor(bus: &PrivateBus, name: &str, idle: Arc<AtomicU64>) {
+        let connection = Connection::new_address(&bus.address).unwrap();
+        connection.request_name(name, false, false, true).unwrap();
+        thread::spawn(move || loop {
+            let message = match connection
+                .channel()
+                .blocking_pop_message(Duration::from_millis(100))
+            {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                /* the bus stopped */
+                Err(_) => return,
+            };
+            let milliseconds = idle.load(Ordering::SeqCst);
+            let reply: Message = match message.member().unwrap().to_string().as_str() {
+                "GetIdletime" => message.method_return().append1(milliseconds),
+                "GetSessionIdleTime" => message
+                    .method_return()
+                    .append1((milliseconds / 1000) as u32),
+                _ => continue,
+            };
+            let _ = connection.channel().send(reply);
+        });
+    }
+
+    #[test]
+    fn should_ask_mutter_for_the_idle_time() {
+        let bus = match PrivateBus::start() {
+            Some(bus) => bus,
+            None => return,
+        };
+        let idle = Arc::new(AtomicU64::new(1500));
+        fake_monitor(&bus, "org.gnome.Mutter.IdleMonitor", idle.clone());
+        fake_monitor(&bus, "org.freedesktop.ScreenSaver", idle);
+
+        let monitor = IdleMonitor::connect(Some(bus.address.clone())).unwrap();
+        assert_eq!(Backend::Mutter, monitor.backend());
+        assert_eq!(Duration::from_millis(1500), monitor.idle_time().unwrap());
+    }
+
+    #[test]
+    fn should_fall_back_to_the_screen_saver() {
+        let bus = match PrivateBus::start() {
+            Some(bus) => bus,
+            None => return,
+        };
+        assert!(IdleMonitor::connect(Some(bus.address.clone())).is_err());
+
+        fake_monitor(
+            &bus,
+            "org.freedesktop.ScreenSaver",
+            Arc::new(AtomicU64::new(61_000)),
+        );
+        let monitor = IdleMonitor::connect(Some(bus.address.clone())).unwrap();
+        assert_eq!(Backend::ScreenSaver, monitor.backend());
+        assert_eq!(Duration::from_secs(61), monitor.idle_time().unwrap());
+    }
+
+    #[test]
+    fn should_report_time_away_once_back() {
+        let mut watch = IdleWatch::new(Duration::from_secs(600));
+        let start = SystemTime::now();
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+
+        assert_eq!(None, watch.update(minutes(9), start + minutes(9)));
+        assert_eq!(None, watch.update(minutes(10), start + minutes(10)));
+        assert_eq!(None, watch.update(minutes(30), start + minutes(30)));
+        assert_eq!(
+            Some(Away {
+                since: start,
+                duration: minutes(40),
+            }),
+            watch.update(Duration::from_secs(0), start + minutes(40))
+        );
+        assert_eq!(None, watch.update(minutes(1), start + minutes(41)));
+    }
+
+    #[test]
+    fn should_count_time_away_from_a_stale_running_timer() {
+        use chrono::TimeZone;
+
+        /* two hours in when retrieved, then asleep for eight hours */
+        let started = chrono::Utc.ymd(2022, 8, 1).and_hms(8, 0, 0);
+        let running: TimeEntry = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "project": {"id": 1, "name": "Project", "code": null, "client": null},
+            "client": {"id": 2, "name": "Client"},
+            "hours": 2.0,
+            "user": {"id": 3},
+            "spent_date": "2022-08-01",
+            "task": {"id": 4, "name": "Development"},
+            "notes": null,
+            "is_running": true,
+            "timer_started_at": "2022-08-01T08:00:00Z",
+            "hours_without_timer": 0.0
+        }))
+        .unwrap();
+        let away = Away {
+            since: SystemTime::from(started + chrono::Duration::hours(2)),
+            duration: Duration::from_secs(8 * 60 * 60),
+        };
+        let now = started + chrono::Duration::hours(10);
+
+        let hours = away.hours_of(&running, now);
+        assert_eq!(8.0, hours);
+        let stopped = running.stopped_without(hours, now);
+        assert!(!stopped.is_running);
+        assert_eq!(2.0, stopped.hours);
+    }
+
+    #[test]
+    fn should_watch_for_the_return() {
+        let bus = match PrivateBus::start() {
+            Some(bus) => bus,
+            None => return,
+        };
+        let idle = Arc::new(AtomicU64::new(20 * 60 * 1000));
+        fake_monitor(&bus, "org.gnome.Mutter.IdleMonitor", idle.clone());
+
+        let (to_test, returns) = mpsc::channel();
+        IdleMonitor::connect(Some(bus.address.clone()))
+            .unwrap()
+            .watch(
+                Duration::from_secs(600),
+                Duration::from_millis(50),
+                move |away| to_test.send(away).unwrap(),
+            );
+        thread::sleep(Duration::from_millis(200));
+        assert!(returns.try_recv().is_err());
+
+        idle.store(0, Ordering::SeqCst);
+        let away = returns.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(away.duration >= Duration::from_secs(20 * 60));
+        assert!(away.duration < Duration::from_secs(21 * 60));
+    }
+}
@@ -0,0 +1,369 @@
+mod mock_server;
+
+#[cfg(test)]
+mod test {
+    use crate::mock_server::{MockServer, ACCOUNT_ID};
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+    use timer_for_harvest::journal::{merge, Change, Journal, Problem};
+    use timer_for_harvest::{Harvest, HarvestError, TimeEntry};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "timer-for-harvest-journal-{}-{}",
+            std::process::id(),
+            name
+        ));
+        path.push("pending_changes.json");
+        path
+    }
+
+    fn today() -> chrono::NaiveDate {
+        chrono::NaiveDate::from_ymd(2022, 8, 1)
+    }
+
+    /* like the application creates them from the project assignments */
+    fn new_time_entry(notes: &str) -> TimeEntry {
+        serde_json::from_value(serde_json::json!({
+            "id": 0,
+            "project": {"id": 1, "name": "Project 1", "code": "P1", "client": null},
+            "client": {"id": 51, "name": "Client 1"},
+            "hours": 0.0,
+            "user": {"id": 1},
+            "spent_date": "2022-08-01",
+            "task": {"id": 10, "name": "Development"},
+            "notes": notes,
+            "is_running": true
+        }))
+        .unwrap()
+    }
+
+    fn unreachable() -> Harvest {
+        Harvest::from_token("token".to_string(), ACCOUNT_ID)
+            .with_api_url("http://127.0.0.1:1/v2")
+            .with_retry_policy(MockServer::retry_policy())
+    }
+
+    #[test]
+    fn should_keep_changes_until_harvest_can_be_reached() {
+        let path = temp_path("offline");
+        let mut journal = Journal::new(path.clone());
+        journal
+            .record(ACCOUNT_ID, Change::Start(new_time_entry("Offline")), None)
+            .unwrap();
+
+        match journal.sync(&unreachable()) {
+            Err(HarvestError::Transport(_)) => {}
+            _ => panic!("expected a transport error"),
+        }
+
+        let mut journal = Journal::load(path.clone()).unwrap();
+        assert!(journal.has_changes(ACCOUNT_ID));
+        assert!(journal.is_local(ACCOUNT_ID, journal.pending(ACCOUNT_ID)[0]));
+
+        let server = MockServer::start();
+        assert!(journal.sync(&server.harvest()).unwrap().is_empty());
+        assert!(!journal.has_changes(ACCOUNT_ID));
+        assert!(!Journal::load(path.clone()).unwrap().has_changes(ACCOUNT_ID));
+
+        let time_entries = server.time_entries();
+        assert_eq!(1, time_entries.len());
+        assert_eq!("Offline", time_entries[0]["notes"]);
+        assert_eq!(true, time_entries[0]["is_running"]);
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_not_start_timers_again_after_a_broken_connection() {
+        /* reads the request, then hangs up without answering */
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = stream.unwrap().read(&mut [0; 4096]);
+            }
+        });
+        let harvest = Harvest::from_token("token".to_string(), ACCOUNT_ID)
+            .with_api_url(&format!("http://127.0.0.1:{}/v2", port))
+            .with_retry_policy(MockServer::retry_policy());
+
+        let mut journal = Journal::new(temp_path("broken"));
+        journal
+            .record(ACCOUNT_ID, Change::Start(new_time_entry("Broken")), None)
+            .unwrap();
+        match journal.sync(&harvest).unwrap().as_slice() {
+            [Problem::Rejected(_, HarvestError::Transport(_))] => {}
+            _ => panic!("expected the start to be dropped"),
+        }
+        assert!(!journal.has_changes(ACCOUNT_ID));
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_keep_changes_while_harvest_is_unavailable() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let running = harvest
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+            .unwrap();
+
+        let mut journal = Journal::new(temp_path("unavailable"));
+        journal
+            .record(ACCOUNT_ID, Change::Stop(running.id), Some(&running))
+            .unwrap();
+        journal
+            .record(ACCOUNT_ID, Change::Start(new_time_entry("Next")), None)
+            .unwrap();
+
+        /* every retry fails as well */
+        for _ in 0..3 {
+            server.fail_next(503, r#"{"message": "Service Unavailable"}"#);
+        }
+        match journal.sync(&harvest) {
+            Err(HarvestError::Status(503, _)) => {}
+            _ => panic!("expected an unavailable Harvest"),
+        }
+        assert_eq!(2, journal.pending(ACCOUNT_ID).len());
+
+        /* longer than the retry policy waits */
+        server.rate_limit_next(60);
+        match journal.sync(&harvest) {
+            Err(HarvestError::Status(429, _)) => {}
+            _ => panic!("expected a rate limit"),
+        }
+        assert_eq!(2, journal.pending(ACCOUNT_ID).len());
+
+        assert!(journal.sync(&harvest).unwrap().is_empty());
+        assert!(!journal.has_changes(ACCOUNT_ID));
+        let time_entries = server.time_entries();
+        assert_eq!(2, time_entries.len());
+        assert_eq!(false, time_entries[0]["is_running"]);
+        assert_eq!(true, time_entries[1]["is_running"]);
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_combine_changes_to_new_time_entries() {
+        let mut journal = Journal::new(temp_path("combine"));
+        journal
+            .record(ACCOUNT_ID, Change::Start(new_time_entry("Draft")), None)
+            .unwrap();
+        let id = journal.pending(ACCOUNT_ID)[0];
+
+        let mut edited = new_time_entry("Final");
+        edited.id = id;
+        journal
+            .record(ACCOUNT_ID, Change::Update(edited), None)
+            .unwrap();
+        journal.record(ACCOUNT_ID, Change::Stop(id), None).unwrap();
+        assert_eq!(vec![id], journal.pending(ACCOUNT_ID));
+
+        let server = MockServer::start();
+        assert!(journal.sync(&server.harvest()).unwrap().is_empty());
+        let time_entries = server.time_entries();
+        assert_eq!(1, time_entries.len());
+        assert_eq!("Final", time_entries[0]["notes"]);
+        assert_eq!(false, time_entries[0]["is_running"]);
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_show_changes_not_saved_yet() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let running = harvest
+            .start_timer(2, 20, "".to_string(), 0.0, &today())
+            .unwrap();
+
+        let mut journal = Journal::new(temp_path("apply"));
+        journal
+            .record(ACCOUNT_ID, Change::Stop(running.id), Some(&running))
+            .unwrap();
+        journal
+            .record(ACCOUNT_ID, Change::Start(new_time_entry("New")), None)
+            .unwrap();
+
+        let mut time_entries = vec![running.clone()];
+        journal.apply(ACCOUNT_ID, "2022-08-01", &mut time_entries);
+        assert_eq!(2, time_entries.len());
+        assert!(!time_entries[0].is_running);
+        assert!(time_entries[1].is_running);
+        assert_eq!(Some("New".to_string()), time_entries[1].notes);
+        /* counted along from when it was started */
+        assert!(time_entries[1].timer_started_at.is_some());
+        assert_eq!(Some(0.0), time_entries[1].hours_without_timer);
+
+        let mut other_day = vec![];
+        journal.apply(ACCOUNT_ID, "2022-08-02", &mut other_day);
+        assert!(other_day.is_empty());
+
+        let mut other_account = vec![running];
+        journal.apply(999, "2022-08-01", &mut other_account);
+        assert!(other_account[0].is_running);
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_report_changes_made_meanwhile() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let time_entry = harvest
+            .start_timer(1, 10, "Original".to_string(), 1.0, &today())
+            .unwrap();
+
+        let mut journal = Journal::new(temp_path("conflict"));
+        let mut edited = time_entry.clone();
+        edited.notes = Some("Mine".to_string());
+        journal
+            .record(ACCOUNT_ID, Change::Update(edited), Some(&time_entry))
+            .unwrap();
+        harvest
+            .update_timer(
+                time_entry.id,
+                1,
+                10,
+                "Theirs".to_string(),
+                1.0,
+                false,
+                "2022-08-01".to_string(),
+            )
+            .unwrap();
+
+        match journal.sync(&harvest).unwrap().as_slice() {
+            [Problem::Conflict(pending, Some(current))] => {
+                assert_eq!(time_entry.id, pending.change.time_entry_id());
+                assert_eq!(Some("Theirs".to_string()), current.notes);
+            }
+            _ => panic!("expected a conflict"),
+        }
+        assert!(!journal.has_changes(ACCOUNT_ID));
+        assert_eq!("Theirs", server.time_entries()[0]["notes"]);
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_stop_timers_changed_meanwhile() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let running = harvest
+            .start_timer(1, 10, "Original".to_string(), 0.0, &today())
+            .unwrap();
+
+        let mut journal = Journal::new(temp_path("stop"));
+        journal
+            .record(ACCOUNT_ID, Change::Stop(running.id), Some(&running))
+            .unwrap();
+        harvest
+            .update_timer(
+                running.id,
+                1,
+                10,
+                "Theirs".to_string(),
+                0.0,
+                true,
+                "2022-08-01".to_string(),
+            )
+            .unwrap();
+
+        assert!(journal.sync(&harvest).unwrap().is_empty());
+        let saved = harvest.time_entry(running.id).unwrap();
+        assert!(!saved.is_running);
+        assert_eq!(Some("Theirs".to_string()), saved.notes);
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_report_deleted_time_entries() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let running = harvest
+            .start_timer(1, 10, "".to_string(), 0.0, &today())
+            .unwrap();
+
+        let mut journal = Journal::new(temp_path("deleted"));
+        journal
+            .record(ACCOUNT_ID, Change::Stop(running.id), Some(&running))
+            .unwrap();
+        harvest.delete_timer(running.id).unwrap();
+
+        match journal.sync(&harvest).unwrap().as_slice() {
+            [Problem::Conflict(_, None)] => {}
+            _ => panic!("expected a deleted time entry"),
+        }
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_report_rejected_changes_and_continue() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+
+        let mut journal = Journal::new(temp_path("rejected"));
+        /* no longer assigned */
+        let mut unassigned = new_time_entry("Unassigned");
+        unassigned.project.id = 999;
+        journal
+            .record(ACCOUNT_ID, Change::Start(unassigned), None)
+            .unwrap();
+        journal
+            .record(ACCOUNT_ID, Change::Start(new_time_entry("Next")), None)
+            .unwrap();
+
+        match journal.sync(&harvest).unwrap().as_slice() {
+            [Problem::Rejected(_, HarvestError::Status(422, _))] => {}
+            _ => panic!("expected a rejected change"),
+        }
+        assert!(!journal.has_changes(ACCOUNT_ID));
+        assert_eq!(1, server.time_entries().len());
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_save_changes_to_time_entries_not_changed_meanwhile() {
+        let server = MockServer::start();
+        let harvest = server.harvest();
+        let time_entry = harvest
+            .start_timer(1, 10, "Original".to_string(), 1.0, &today())
+            .unwrap();
+
+        let mut journal = Journal::new(temp_path("unchanged"));
+        let mut edited = time_entry.clone();
+        edited.notes = Some("Mine".to_string());
+        edited.hours = 2.0;
+        journal
+            .record(ACCOUNT_ID, Change::Update(edited), Some(&time_entry))
+            .unwrap();
+
+        assert!(journal.sync(&harvest).unwrap().is_empty());
+        let saved = harvest.time_entry(time_entry.id).unwrap();
+        assert_eq!(Some("Mine".to_string()), saved.notes);
+        assert_eq!(2.0, saved.hours);
+        assert!(saved.changed_since(&time_entry));
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn should_merge_changes_to_different_fields() {
+        let mut base = new_time_entry("Original");
+        base.is_running = false;
+        base.hours = 1.0;
+
+        let mut mine = base.clone();
+        mine.notes = Some("Mine".to_string());
+        let mut theirs = base.clone();
+        theirs.hours = 2.5;
+        theirs.notes = Some("Theirs".to_string());
+        theirs.task.id = 11;
+        theirs.task.name = "Meetings".to_string();
+
+        let merged = merge(&base, &mine, &theirs);
+        assert_eq!(Some("Mine".to_string()), merged.notes);
+        assert_eq!(2.5, merged.hours);
+        assert_eq!(11, merged.task.id);
+
+        mine.hours = 3.0;
+        assert_eq!(3.0, merge(&base, &mine, &theirs).hours);
+    }
+}
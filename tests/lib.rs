@@ -1,5 +1,7 @@
 #[cfg(test)]
 mod test {
+    use timer_for_harvest::{parse_account_details, AuthCallback};
+
     #[test]
     fn should_convert_duration_correctly() {
         assert_eq!("1:00", timer_for_harvest::f32_to_duration_str(1.0));
@@ -16,44 +18,98 @@ mod test {
         assert_eq!(1.0, timer_for_harvest::duration_str_to_f32("1"));
     }
 
+    fn parse(request: &str) -> AuthCallback {
+        parse_account_details(request, "xyz").unwrap()
+    }
+
+    fn authorized(token: &str, account_ids: Vec<u32>, expires_in: u64) -> AuthCallback {
+        AuthCallback::Authorized {
+            token: token.to_string(),
+            account_ids: account_ids,
+            expires_in: expires_in,
+        }
+    }
+
     #[test]
-    fn should_parse_account_id() {
+    fn should_parse_account_details() {
         assert_eq!(
-            "123",
-            timer_for_harvest::parse_account_details("GET /?access_token=abc&scope=harvest%3A123")
-                .1
+            authorized("abc", vec![123], 456),
+            parse("GET /?access_token=abc&scope=harvest%3A123&expires_in=456&state=xyz HTTP/1.1")
         );
         assert_eq!(
-            "123",
-            timer_for_harvest::parse_account_details("GET /?scope=harvest%3A123&access_token=abc")
-                .1
+            authorized("abc", vec![123], 456),
+            parse("GET /?state=xyz&expires_in=456&scope=harvest%3A123&access_token=abc HTTP/1.1")
         );
     }
 
     #[test]
-    fn should_parse_access_token() {
+    fn should_parse_multiple_account_ids() {
         assert_eq!(
-            "abc",
-            timer_for_harvest::parse_account_details("GET /?access_token=abc&scope=harvest%3A123")
-                .0
+            authorized("abc", vec![123, 456], 60),
+            parse("GET /?access_token=abc&scope=harvest%3A123+forecast%3A789+harvest%3A456&expires_in=60&state=xyz")
         );
         assert_eq!(
-            "abc",
-            timer_for_harvest::parse_account_details("GET /?scope=harvest%3A123&access_token=abc")
-                .0
+            authorized("abc", vec![123, 456], 60),
+            parse("GET /?access_token=abc&scope=harvest:123%20harvest:456&expires_in=60&state=xyz")
         );
     }
 
     #[test]
-    fn should_parse_expires_in() {
+    fn should_decode_access_token() {
         assert_eq!(
-            "123",
-            timer_for_harvest::parse_account_details("GET /?expires_in=123&scope=harvest%3A456").2
+            authorized("a.b/c=", vec![123], 60),
+            parse("GET /?access_token=a.b%2Fc%3D&scope=harvest%3A123&expires_in=60&state=xyz")
         );
+    }
+
+    #[test]
+    fn should_parse_oauth_error() {
         assert_eq!(
-            "123",
-            timer_for_harvest::parse_account_details("GET /?scope=harvest%3A456&expires_in=123").2
+            AuthCallback::Error {
+                error: "access_denied".to_string(),
+                description: Some("The user denied access".to_string()),
+            },
+            parse("GET /?error=access_denied&error_description=The+user+denied+access&state=xyz")
+        );
+    }
+
+    #[test]
+    fn should_verify_state() {
+        assert!(parse_account_details(
+            "GET /?access_token=abc&scope=harvest%3A123&expires_in=60&state=other",
+            "xyz"
+        )
+        .is_err());
+        assert!(parse_account_details(
+            "GET /?access_token=abc&scope=harvest%3A123&expires_in=60",
+            "xyz"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn should_not_crash_on_incomplete_account_details() {
+        assert!(parse_account_details("GET /", "xyz").is_err());
+        assert!(parse_account_details("", "xyz").is_err());
+        assert!(
+            parse_account_details("GET /?state=xyz&scope=harvest%3A123&expires_in=60", "xyz")
+                .is_err()
         );
+        assert!(parse_account_details(
+            "GET /?state=xyz&access_token=abc&scope=harvest%3A&expires_in=60",
+            "xyz"
+        )
+        .is_err());
+        assert!(parse_account_details(
+            "GET /?state=xyz&access_token=abc&scope=harvest%3A123",
+            "xyz"
+        )
+        .is_err());
+        assert!(parse_account_details(
+            "GET /?state=xyz&access_token=abc&scope=harvest%3A123&expires_in=%ZZ",
+            "xyz"
+        )
+        .is_err());
     }
 
     #[test]
@@ -61,4 +117,302 @@ mod test {
         assert_eq!("Lorem Ipsum - 1234567890 - 1234567890 - 1234567890 - 1234567890 - 1234567890 - 1...", timer_for_harvest::format_timeentry_notes_for_list(&"Lorem Ipsum\n\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890", None));
         assert_eq!("Lorem Ipsum - 1...", timer_for_harvest::format_timeentry_notes_for_list(&"Lorem Ipsum\n\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890\n1234567890", Some(15)));
     }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "timer-for-harvest-test-{}-{}",
+            std::process::id(),
+            name
+        ));
+        path
+    }
+
+    fn authorization() -> timer_for_harvest::Authorization {
+        timer_for_harvest::Authorization {
+            token: "abc".to_string(),
+            account_id: 123,
+            expires_at: 1600000000,
+            account_ids: vec![123, 456],
+        }
+    }
+
+    #[test]
+    fn should_store_credentials_only_readable_by_user() {
+        use std::os::unix::fs::PermissionsExt;
+        use timer_for_harvest::credentials::{CredentialStore, FileStore};
+
+        let path = temp_path("store.json");
+        std::fs::write(&path, "{}").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let store = FileStore::new(path.clone());
+        store.store(&authorization()).unwrap();
+        assert_eq!(
+            0o600,
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+        assert_eq!(Some(authorization()), store.load().unwrap());
+
+        store.clear().unwrap();
+        assert!(!path.exists());
+        assert_eq!(None, store.load().unwrap());
+    }
+
+    #[test]
+    fn should_not_show_tokens_of_invalid_configuration_files() {
+        use timer_for_harvest::credentials::{CredentialStore, FileStore};
+        use timer_for_harvest::HarvestError;
+
+        let path = temp_path("invalid.json");
+        std::fs::write(&path, "{\"token\":\"secret\",\"account_id\":\"secret\"}").unwrap();
+
+        match FileStore::new(path.clone()).load() {
+            Err(HarvestError::Credentials(message)) => {
+                assert!(message.contains(&path.display().to_string()));
+                assert!(!message.contains("secret"));
+            }
+            _ => panic!("expected an invalid configuration file"),
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_load_authorization_of_earlier_versions() {
+        use timer_for_harvest::credentials::{CredentialStore, FileStore};
+
+        let path = temp_path("earlier.json");
+        std::fs::write(
+            &path,
+            "{\"token\":\"abc\",\"account_id\":123,\"expires_at\":1600000000}",
+        )
+        .unwrap();
+
+        let authorization = FileStore::new(path.clone()).load().unwrap().unwrap();
+        assert_eq!(vec![123], authorization.account_ids());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn should_use_access_token_from_environment() {
+        use timer_for_harvest::Harvest;
+
+        std::env::set_var("TFH_ACCESS_TOKEN", " abc\n");
+        std::env::set_var("TFH_ACCOUNT_ID", "123");
+        let harvest = Harvest::from_environment().unwrap();
+        assert_eq!("abc", harvest.authorization().token);
+        assert_eq!(123, harvest.account_id());
+        assert_eq!(u64::MAX, harvest.authorization().expires_at);
+
+        std::env::set_var("TFH_ACCOUNT_ID", "not a number");
+        assert!(Harvest::from_environment().is_none());
+        std::env::remove_var("TFH_ACCESS_TOKEN");
+        std::env::remove_var("TFH_ACCOUNT_ID");
+        assert!(Harvest::from_environment().is_none());
+    }
+
+    #[test]
+    fn should_calculate_days_until_expiry() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut authorization = authorization();
+
+        authorization.expires_at = now + 60 * 60 * 24 * 14 - 60;
+        assert_eq!(Some(13), authorization.expires_in_days());
+        authorization.expires_at = now + 60 * 60;
+        assert_eq!(Some(0), authorization.expires_in_days());
+        authorization.expires_at = now - 60;
+        assert_eq!(Some(0), authorization.expires_in_days());
+        authorization.expires_at = u64::MAX;
+        assert_eq!(None, authorization.expires_in_days());
+    }
+
+    #[test]
+    fn should_migrate_credentials_once() {
+        use timer_for_harvest::credentials::{migrate, CredentialStore, FileStore};
+
+        let old = FileStore::new(temp_path("old.json"));
+        let new = FileStore::new(temp_path("new.json"));
+        old.store(&authorization()).unwrap();
+
+        migrate(&old, &new).unwrap();
+        assert_eq!(None, old.load().unwrap());
+        assert_eq!(Some(authorization()), new.load().unwrap());
+
+        migrate(&old, &new).unwrap();
+        assert_eq!(Some(authorization()), new.load().unwrap());
+        new.clear().unwrap();
+    }
+
+    fn time_entry(id: u32, spent_date: &str) -> timer_for_harvest::TimeEntry {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "project": {"id": 1, "name": "Project", "code": null, "client": null},
+            "client": {"id": 2, "name": "Client"},
+            "hours": 1.5,
+            "user": {"id": 3},
+            "spent_date": spent_date,
+            "task": {"id": 4, "name": "Development"},
+            "notes": "Notes",
+            "is_running": false
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn should_cache_time_entries_per_account_and_day() {
+        use timer_for_harvest::cache::Cache;
+
+        let cache = Cache::new(temp_path("cache"));
+        let date = chrono::NaiveDate::from_ymd(2022, 8, 1);
+        assert!(cache.time_entries(123, &date).is_none());
+
+        cache
+            .store_time_entries(123, &date, &[time_entry(1, "2022-08-01")])
+            .unwrap();
+        let time_entries = cache.time_entries(123, &date).unwrap();
+        assert_eq!(1, time_entries.len());
+        assert_eq!(Some("Notes".to_string()), time_entries[0].notes);
+        assert!(cache.time_entries(456, &date).is_none());
+        assert!(cache.time_entries(123, &date.succ()).is_none());
+
+        cache.clear().unwrap();
+        assert!(cache.time_entries(123, &date).is_none());
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn should_only_cache_recent_days() {
+        use timer_for_harvest::cache::Cache;
+
+        let path = temp_path("recent-cache");
+        let cache = Cache::new(path.clone());
+        let mut date = chrono::NaiveDate::from_ymd(2022, 8, 1);
+        for _ in 0..40 {
+            cache.store_time_entries(123, &date, &[]).unwrap();
+            date = date.succ();
+        }
+
+        assert_eq!(
+            31,
+            std::fs::read_dir(path.join("123").join("time_entries"))
+                .unwrap()
+                .count()
+        );
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn should_cache_account() {
+        use timer_for_harvest::cache::{Cache, CachedAccount};
+
+        let cache = Cache::new(temp_path("account-cache"));
+        assert!(cache.account(123).is_none());
+
+        cache
+            .store_account(
+                123,
+                &CachedAccount {
+                    user: timer_for_harvest::User { id: 3 },
+                    project_assignments: vec![],
+                },
+            )
+            .unwrap();
+        assert_eq!(3, cache.account(123).unwrap().user.id);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn should_cache_only_readable_by_user() {
+        use std::os::unix::fs::PermissionsExt;
+        use timer_for_harvest::cache::{Cache, CachedAccount};
+
+        /* created by someone else, or an earlier version */
+        let dir = temp_path("private-cache");
+        std::fs::create_dir_all(dir.join("123")).unwrap();
+        std::fs::set_permissions(dir.join("123"), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cache = Cache::new(dir.clone());
+        cache
+            .store_account(
+                123,
+                &CachedAccount {
+                    user: timer_for_harvest::User { id: 3 },
+                    project_assignments: vec![],
+                },
+            )
+            .unwrap();
+        let mode = std::fs::metadata(dir.join("123").join("account.json"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(0o600, mode & 0o777);
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn should_compare_time_entries_by_update_time() {
+        let mut earlier = time_entry(1, "2022-08-01");
+        let mut later = earlier.clone();
+        later.hours = 2.0;
+        assert!(later.changed_since(&earlier));
+
+        earlier.updated_at = Some("2022-08-01T10:00:00Z".to_string());
+        later.updated_at = Some("2022-08-01T10:00:00Z".to_string());
+        assert!(!later.changed_since(&earlier));
+
+        later.updated_at = Some("2022-08-01T11:30:00Z".to_string());
+        assert!(later.changed_since(&earlier));
+        assert!(!earlier.changed_since(&later));
+    }
+
+    #[test]
+    fn should_count_running_hours_from_the_start() {
+        use chrono::TimeZone;
+
+        let started = chrono::Utc.ymd(2022, 8, 1).and_hms(10, 0, 0);
+        let mut time_entry = time_entry(1, "2022-08-01");
+        assert_eq!(1.5, time_entry.hours_at(started));
+
+        time_entry.timer_started_at = Some("2022-08-01T10:00:00Z".to_string());
+        time_entry.hours_without_timer = Some(1.0);
+        time_entry.is_running = true;
+        assert_eq!(1.0, time_entry.hours_at(started));
+        assert_eq!(
+            1.5,
+            time_entry.hours_at(started + chrono::Duration::minutes(30))
+        );
+
+        time_entry.stop_timer_at(started + chrono::Duration::minutes(45));
+        assert!(!time_entry.is_running);
+        assert_eq!(
+            1.75,
+            time_entry.hours_at(started + chrono::Duration::hours(5))
+        );
+
+        time_entry.start_timer_at(started + chrono::Duration::hours(1));
+        assert_eq!(
+            2.25,
+            time_entry
+                .hours_at(started + chrono::Duration::hours(2) - chrono::Duration::minutes(30))
+        );
+    }
+
+    #[test]
+    fn should_convert_duration_with_seconds() {
+        assert_eq!(
+            "0:00:00",
+            timer_for_harvest::f32_to_duration_with_seconds_str(0.0)
+        );
+        assert_eq!(
+            "1:30:05",
+            timer_for_harvest::f32_to_duration_with_seconds_str(1.5 + 5.0 / 3600.0)
+        );
+        assert_eq!(
+            "0:01:00",
+            timer_for_harvest::f32_to_duration_with_seconds_str(59.9 / 3600.0)
+        );
+    }
 }
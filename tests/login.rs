@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use std::time::Duration;
+    use timer_for_harvest::login::LoginServer;
+    use timer_for_harvest::HarvestError;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /* acts like the browser being redirected back, in parts to test partial reads */
+    fn browse(port: u16, parts: Vec<String>) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            for part in parts {
+                stream.write_all(part.as_bytes()).unwrap();
+                stream.flush().unwrap();
+                thread::sleep(Duration::from_millis(20));
+            }
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        })
+    }
+
+    fn get(target: &str) -> String {
+        format!(
+            "GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\nUser-Agent: {}\r\nAccept: text/html\r\n\r\n",
+            target,
+            "Mozilla/5.0 ".repeat(100)
+        )
+    }
+
+    #[test]
+    fn should_receive_authorization_from_redirect() {
+        let server = LoginServer::bind_to(0).unwrap();
+        let request = get(&format!(
+            "/?access_token=abc.def&scope=harvest%3A123&expires_in=1209600&token_type=bearer&state={}",
+            server.state()
+        ));
+        let (first, second) = request.split_at(30);
+        let browser = browse(server.port(), vec![first.to_string(), second.to_string()]);
+
+        let authorization = server.wait(TIMEOUT, &AtomicBool::new(false)).unwrap();
+        assert_eq!("abc.def", authorization.token);
+        assert_eq!(123, authorization.account_id);
+
+        let response = browser.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/html"));
+        assert!(response.contains("Signed in to Harvest successfully"));
+    }
+
+    #[test]
+    fn should_ignore_other_requests() {
+        let server = LoginServer::bind_to(0).unwrap();
+        let port = server.port();
+        let redirect = format!(
+            "/?access_token=abc&scope=harvest%3A123&expires_in=60&state={}",
+            server.state()
+        );
+        let browser = thread::spawn(move || {
+            let favicon = browse(port, vec![get("/favicon.ico")]).join().unwrap();
+            browse(port, vec![get(&redirect)]).join().unwrap();
+            favicon
+        });
+
+        let authorization = server.wait(TIMEOUT, &AtomicBool::new(false)).unwrap();
+        assert_eq!("abc", authorization.token);
+        assert!(browser
+            .join()
+            .unwrap()
+            .starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn should_report_denied_access() {
+        let server = LoginServer::bind_to(0).unwrap();
+        let browser = browse(
+            server.port(),
+            vec![get(&format!(
+                "/?error=access_denied&error_description=Denied&state={}",
+                server.state()
+            ))],
+        );
+
+        match server.wait(TIMEOUT, &AtomicBool::new(false)) {
+            Err(HarvestError::Login(message)) => assert_eq!("Denied", message),
+            _ => panic!("expected a login error"),
+        }
+        assert!(browser
+            .join()
+            .unwrap()
+            .starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn should_send_state_to_harvest() {
+        let server = LoginServer::bind_to(0).unwrap();
+        let other = LoginServer::bind_to(0).unwrap();
+
+        assert_eq!(32, server.state().len());
+        assert_ne!(server.state(), other.state());
+        assert!(server
+            .authorize_url()
+            .contains(&format!("&state={}", server.state())));
+    }
+
+    #[test]
+    fn should_stop_waiting_when_cancelled() {
+        let server = LoginServer::bind_to(0).unwrap();
+
+        match server.wait(TIMEOUT, &AtomicBool::new(true)) {
+            Err(HarvestError::LoginCancelled) => {}
+            _ => panic!("expected a cancelled login"),
+        }
+    }
+
+    #[test]
+    fn should_stop_waiting_after_timeout() {
+        let server = LoginServer::bind_to(0).unwrap();
+
+        match server.wait(Duration::from_millis(200), &AtomicBool::new(false)) {
+            Err(HarvestError::Login(_)) => {}
+            _ => panic!("expected a timed out login"),
+        }
+    }
+
+    #[test]
+    fn should_keep_waiting_after_redirects_of_other_sign_ins() {
+        let server = LoginServer::bind_to(0).unwrap();
+        let port = server.port();
+        let redirect = format!(
+            "/?access_token=abc&scope=harvest%3A123&expires_in=60&state={}",
+            server.state()
+        );
+        let browser = thread::spawn(move || {
+            let other = browse(
+                port,
+                vec![get(
+                    "/?access_token=xyz&scope=harvest%3A456&expires_in=60&state=other",
+                )],
+            )
+            .join()
+            .unwrap();
+            browse(port, vec![get(&redirect)]).join().unwrap();
+            other
+        });
+
+        let authorization = server.wait(TIMEOUT, &AtomicBool::new(false)).unwrap();
+        assert_eq!("abc", authorization.token);
+        assert!(browser
+            .join()
+            .unwrap()
+            .starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn should_report_port_in_use() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken_port = taken.local_addr().unwrap().port();
+
+        match LoginServer::bind_to(taken_port) {
+            Err(HarvestError::Login(message)) => {
+                assert!(message.starts_with(&format!("Port {} is in use", taken_port)))
+            }
+            _ => panic!("expected a login error"),
+        }
+        assert!(!LoginServer::bind_to(0)
+            .unwrap()
+            .authorize_url()
+            .contains("redirect_uri"));
+    }
+}
@@ -0,0 +1,518 @@
+/* a small in-memory stand-in for the subset of the Harvest v2 API used by the application */
+/* shared by several test crates, which each use only part of it */
+#![allow(dead_code)]
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use timer_for_harvest::{Harvest, RetryPolicy};
+
+pub const TOKEN: &str = "mock-token";
+pub const ACCOUNT_ID: u32 = 4321;
+pub const OTHER_ACCOUNT_ID: u32 = 8765;
+pub const USER_ID: u32 = 1;
+/* the same person has a different user id in each account */
+pub const OTHER_USER_ID: u32 = 2;
+/* small pages, so pagination is exercised without needing a lot of records */
+pub const PER_PAGE: usize = 2;
+
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub body: Value,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+struct State {
+    origin: String,
+    project_assignments: Vec<Value>,
+    time_entries: Vec<Value>,
+    next_id: u32,
+    /* every change gets a later updated_at */
+    updates: u32,
+    failures: VecDeque<Response>,
+    requests: Vec<String>,
+}
+
+pub struct MockServer {
+    pub url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    /* starts listening on a random local port, the server lives until the test process exits */
+    pub fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Unable to bind mock server");
+        let origin = format!("http://{}", listener.local_addr().unwrap());
+        let url = format!("{}/v2", origin);
+        let state = Arc::new(Mutex::new(State {
+            origin: origin,
+            project_assignments: MockServer::default_project_assignments(),
+            time_entries: vec![],
+            next_id: 1000,
+            updates: 0,
+            failures: VecDeque::new(),
+            requests: vec![],
+        }));
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let state = server_state.clone();
+                thread::spawn(move || MockServer::handle_connection(stream, state));
+            }
+        });
+
+        MockServer {
+            url: url,
+            state: state,
+        }
+    }
+
+    /* retries quickly, so tests for failing requests do not take long */
+    pub fn harvest(&self) -> Harvest {
+        Harvest::from_token(TOKEN.to_string(), ACCOUNT_ID)
+            .with_api_url(&self.url)
+            .with_id_url(&self.state.lock().unwrap().origin)
+            .with_retry_policy(MockServer::retry_policy())
+    }
+
+    pub fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+
+    /* the next request, whatever it is, is answered with this response */
+    pub fn fail_next(&self, status: u16, body: &str) {
+        self.state.lock().unwrap().failures.push_back(Response {
+            status: status,
+            headers: vec![],
+            body: body.to_string(),
+        });
+    }
+
+    /* answer the next request like Harvest does when exceeding the rate limit */
+    pub fn rate_limit_next(&self, retry_after: u64) {
+        self.state.lock().unwrap().failures.push_back(Response {
+            status: 429,
+            headers: vec![("Retry-After".to_string(), retry_after.to_string())],
+            body: json!({"message": "Too Many Requests"}).to_string(),
+        });
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn time_entries(&self) -> Vec<Value> {
+        self.state.lock().unwrap().time_entries.clone()
+    }
+
+    pub fn set_project_assignment_count(&self, count: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.project_assignments = (1..=count).map(MockServer::project_assignment).collect();
+    }
+
+    fn default_project_assignments() -> Vec<Value> {
+        (1..=5).map(MockServer::project_assignment).collect()
+    }
+
+    fn project_assignment(id: u32) -> Value {
+        json!({
+            "id": 100 + id,
+            "project": {"id": id, "name": format!("Project {}", id), "code": format!("P{}", id)},
+            "client": {"id": 50 + id, "name": format!("Client {}", id)},
+            "task_assignments": [
+                {"id": 200 + id * 10, "task": {"id": 10 * id, "name": "Development"}},
+                {"id": 201 + id * 10, "task": {"id": 10 * id + 1, "name": "Meetings"}}
+            ]
+        })
+    }
+
+    fn handle_connection(stream: TcpStream, state: Arc<Mutex<State>>) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let request = match MockServer::read_request(&mut reader) {
+            Some(request) => request,
+            None => return,
+        };
+        let response = MockServer::respond(&request, &mut state.lock().unwrap());
+        MockServer::write_response(stream, response);
+    }
+
+    fn read_request(reader: &mut BufReader<TcpStream>) -> Option<Request> {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).ok()?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?.to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).ok()?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let mut header = line.splitn(2, ':');
+            let name = header.next()?.trim().to_lowercase();
+            let value = header.next().unwrap_or("").trim().to_string();
+            headers.insert(name, value);
+        }
+
+        let length: usize = headers
+            .get("content-length")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).ok()?;
+
+        let mut parts = target.splitn(2, '?');
+        let path = parts.next()?.to_string();
+        let query = parts
+            .next()
+            .unwrap_or("")
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let mut pair = p.splitn(2, '=');
+                (
+                    pair.next().unwrap().to_string(),
+                    pair.next().unwrap_or("").to_string(),
+                )
+            })
+            .collect();
+
+        Some(Request {
+            method: method,
+            target: target,
+            path: path,
+            query: query,
+            headers: headers,
+            body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+        })
+    }
+
+    fn write_response(mut stream: TcpStream, response: Response) {
+        let mut head = format!(
+            "HTTP/1.1 {} Mock\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n",
+            response.status,
+            response.body.len()
+        );
+        for (name, value) in response.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        let _ = stream.write_all(head.as_bytes());
+        let _ = stream.write_all(response.body.as_bytes());
+        let _ = stream.flush();
+    }
+
+    fn respond(request: &Request, state: &mut State) -> Response {
+        state
+            .requests
+            .push(format!("{} {}", request.method, request.target));
+
+        if let Some(failure) = state.failures.pop_front() {
+            return failure;
+        }
+
+        let authorization = request.headers.get("authorization");
+        if authorization != Some(&format!("Bearer {}", TOKEN)) {
+            return MockServer::json(
+                401,
+                json!({"error": "invalid_token", "error_description": "The access token provided is expired, revoked, malformed or invalid for other reasons."}),
+            );
+        }
+
+        /* served by id.getharvest.com, which does not need an account */
+        if request.method == "GET" && request.path == "/api/v2/accounts" {
+            return MockServer::json(
+                200,
+                json!({
+                    "user": {"id": 99, "first_name": "Mock", "last_name": "User"},
+                    "accounts": [
+                        {"id": ACCOUNT_ID, "name": "Mock Company", "product": "harvest"},
+                        {"id": 1234, "name": "Mock Company", "product": "forecast"},
+                        {"id": OTHER_ACCOUNT_ID, "name": "Other Company", "product": "harvest"}
+                    ]
+                }),
+            );
+        }
+
+        let user_id = match request.headers.get("harvest-account-id") {
+            Some(id) if id == &ACCOUNT_ID.to_string() => USER_ID,
+            Some(id) if id == &OTHER_ACCOUNT_ID.to_string() => OTHER_USER_ID,
+            _ => return MockServer::json(403, json!({"message": "Unknown account"})),
+        };
+
+        let path = match request.path.strip_prefix("/v2") {
+            Some(path) => path.to_string(),
+            None => return MockServer::not_found(),
+        };
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["users", "me"]) => MockServer::json(
+                200,
+                json!({"id": user_id, "first_name": "Mock", "last_name": "User"}),
+            ),
+            ("GET", ["users", "me", "project_assignments"]) => MockServer::page(
+                "project_assignments",
+                &state.origin,
+                request,
+                &state.project_assignments,
+            ),
+            ("GET", ["time_entries"]) => {
+                let from = request.query.get("from").cloned().unwrap_or_default();
+                let to = request.query.get("to").cloned().unwrap_or_default();
+                let is_running = request.query.get("is_running").cloned();
+                let time_entries: Vec<Value> = state
+                    .time_entries
+                    .iter()
+                    .filter(|t| {
+                        let spent_date = t["spent_date"].as_str().unwrap();
+                        (from.is_empty() || spent_date >= from.as_str())
+                            && (to.is_empty() || spent_date <= to.as_str())
+                            && (is_running.is_none()
+                                || is_running == Some(t["is_running"].to_string()))
+                    })
+                    .cloned()
+                    .collect();
+                MockServer::page("time_entries", &state.origin, request, &time_entries)
+            }
+            ("GET", ["time_entries", id]) => {
+                MockServer::with_time_entry(state, id, |state, index| {
+                    MockServer::json(200, state.time_entries[index].clone())
+                })
+            }
+            ("POST", ["time_entries"]) => MockServer::create_time_entry(request, state),
+            ("PATCH", ["time_entries", id]) => {
+                MockServer::with_time_entry(state, id, |state, index| {
+                    let entry = &mut state.time_entries[index];
+                    for field in &["notes", "spent_date", "hours"] {
+                        if !request.body[*field].is_null() {
+                            entry[*field] = request.body[*field].clone();
+                        }
+                    }
+                    if let Some(project) = MockServer::find_project(
+                        &state.project_assignments,
+                        &request.body["project_id"],
+                    ) {
+                        entry["project"] = project["project"].clone();
+                        entry["client"] = project["client"].clone();
+                    }
+                    if let Some(task) =
+                        MockServer::find_task(&state.project_assignments, &request.body["task_id"])
+                    {
+                        entry["task"] = task.clone();
+                    }
+                    MockServer::touch(state, index);
+                    MockServer::json(200, state.time_entries[index].clone())
+                })
+            }
+            ("PATCH", ["time_entries", id, "restart"]) => {
+                MockServer::with_time_entry(state, id, |state, index| {
+                    if state.time_entries[index]["is_running"] == json!(true) {
+                        return MockServer::json(
+                            422,
+                            json!({"message": "Cannot restart a running time entry"}),
+                        );
+                    }
+                    MockServer::stop_running(state);
+                    state.time_entries[index]["is_running"] = json!(true);
+                    MockServer::touch(state, index);
+                    MockServer::json(200, state.time_entries[index].clone())
+                })
+            }
+            ("PATCH", ["time_entries", id, "stop"]) => {
+                MockServer::with_time_entry(state, id, |state, index| {
+                    if state.time_entries[index]["is_running"] == json!(false) {
+                        return MockServer::json(
+                            422,
+                            json!({"message": "Cannot stop a stopped time entry"}),
+                        );
+                    }
+                    state.time_entries[index]["is_running"] = json!(false);
+                    MockServer::touch(state, index);
+                    MockServer::json(200, state.time_entries[index].clone())
+                })
+            }
+            ("DELETE", ["time_entries", id]) => {
+                MockServer::with_time_entry(state, id, |state, index| {
+                    let entry = state.time_entries.remove(index);
+                    MockServer::json(200, entry)
+                })
+            }
+            _ => MockServer::not_found(),
+        }
+    }
+
+    fn create_time_entry(request: &Request, state: &mut State) -> Response {
+        let project =
+            match MockServer::find_project(&state.project_assignments, &request.body["project_id"])
+            {
+                Some(project) => project.clone(),
+                None => {
+                    return MockServer::json(
+                        422,
+                        json!({"message": "Project is not assigned to the current user"}),
+                    )
+                }
+            };
+        let task =
+            match MockServer::find_task(std::slice::from_ref(&project), &request.body["task_id"]) {
+                Some(task) => task.clone(),
+                None => {
+                    return MockServer::json(
+                        422,
+                        json!({"message": "Task is not assigned to the project"}),
+                    )
+                }
+            };
+
+        /* like Harvest, entries with hours are not started */
+        let is_running = request.body["hours"].is_null();
+        if is_running {
+            MockServer::stop_running(state);
+        }
+
+        state.next_id += 1;
+        let entry = json!({
+            "id": state.next_id,
+            "project": project["project"],
+            "client": project["client"],
+            "task": task,
+            "user": {"id": USER_ID},
+            "hours": request.body["hours"].as_f64().unwrap_or(0.0),
+            "spent_date": request.body["spent_date"],
+            "notes": request.body["notes"],
+            "is_running": is_running,
+        });
+        state.time_entries.push(entry);
+        let index = state.time_entries.len() - 1;
+        MockServer::touch(state, index);
+        MockServer::json(201, state.time_entries[index].clone())
+    }
+
+    fn find_project<'a>(project_assignments: &'a [Value], id: &Value) -> Option<&'a Value> {
+        project_assignments
+            .iter()
+            .find(|p| !id.is_null() && &p["project"]["id"] == id)
+    }
+
+    fn find_task<'a>(project_assignments: &'a [Value], id: &Value) -> Option<&'a Value> {
+        project_assignments
+            .iter()
+            .flat_map(|p| p["task_assignments"].as_array().unwrap().iter())
+            .map(|t| &t["task"])
+            .find(|t| !id.is_null() && &t["id"] == id)
+    }
+
+    fn with_time_entry<F>(state: &mut State, id: &str, action: F) -> Response
+    where
+        F: FnOnce(&mut State, usize) -> Response,
+    {
+        let id: u64 = id.parse().unwrap_or(0);
+        match state
+            .time_entries
+            .iter()
+            .position(|t| t["id"].as_u64() == Some(id))
+        {
+            Some(index) => action(state, index),
+            None => MockServer::not_found(),
+        }
+    }
+
+    fn stop_running(state: &mut State) {
+        for index in 0..state.time_entries.len() {
+            if state.time_entries[index]["is_running"] == json!(true) {
+                state.time_entries[index]["is_running"] = json!(false);
+                MockServer::touch(state, index);
+            }
+        }
+    }
+
+    fn touch(state: &mut State, index: usize) {
+        state.updates += 1;
+        state.time_entries[index]["updated_at"] = json!(format!(
+            "2022-08-01T{:02}:{:02}:{:02}Z",
+            state.updates / 3600,
+            state.updates / 60 % 60,
+            state.updates % 60
+        ));
+    }
+
+    fn page(key: &str, origin: &str, request: &Request, records: &[Value]) -> Response {
+        let page: usize = request
+            .query
+            .get("page")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(1);
+        let total_pages = std::cmp::max(1, records.len().div_ceil(PER_PAGE));
+        let on_page: Vec<Value> = records
+            .iter()
+            .skip((page - 1) * PER_PAGE)
+            .take(PER_PAGE)
+            .cloned()
+            .collect();
+
+        let link = |page: usize| {
+            let mut query: Vec<String> = request
+                .query
+                .iter()
+                .filter(|(name, _)| *name != "page")
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            query.sort();
+            query.push(format!("page={}", page));
+            format!("{}{}?{}", origin, request.path, query.join("&"))
+        };
+
+        let mut body = json!({
+            "per_page": PER_PAGE,
+            "total_pages": total_pages,
+            "total_entries": records.len(),
+            "page": page,
+            "links": {
+                "first": link(1),
+                "next": if page < total_pages { Some(link(page + 1)) } else { None },
+                "previous": if page > 1 { Some(link(page - 1)) } else { None },
+                "last": link(total_pages),
+            },
+        });
+        body[key] = json!(on_page);
+        MockServer::json(200, body)
+    }
+
+    fn json(status: u16, body: Value) -> Response {
+        Response {
+            status: status,
+            headers: vec![],
+            body: body.to_string(),
+        }
+    }
+
+    fn not_found() -> Response {
+        MockServer::json(404, json!({"message": "Not found"}))
+    }
+}
@@ -0,0 +1,43 @@
+/* shared by several test crates, not all of them use everything */
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+
+/* a private bus, so the tests do not depend on or disturb the session bus */
+pub struct PrivateBus {
+    daemon: Child,
+    pub address: String,
+}
+
+impl PrivateBus {
+    /* None when dbus-daemon is not installed */
+    pub fn start() -> Option<PrivateBus> {
+        let mut daemon = match Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(daemon) => daemon,
+            Err(e) => {
+                eprintln!("Skipped, unable to start dbus-daemon: {}", e);
+                return None;
+            }
+        };
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Some(PrivateBus {
+            daemon: daemon,
+            address: address.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
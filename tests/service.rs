@@ -0,0 +1,117 @@
+mod private_bus;
+
+#[cfg(test)]
+mod test {
+    use crate::private_bus::PrivateBus;
+    use dbus::arg::{PropMap, RefArg};
+    use dbus::blocking::Connection;
+    use dbus::message::MatchRule;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use timer_for_harvest::service::{Request, Service, INTERFACE, NAME, PATH};
+    use timer_for_harvest::TimeEntry;
+
+    fn running() -> TimeEntry {
+        serde_json::from_value(serde_json::json!({
+            "id": 7,
+            "project": {"id": 1, "name": "Project 1", "code": "P1", "client": null},
+            "client": {"id": 51, "name": "Client 1"},
+            "hours": 1.5,
+            "user": {"id": 1},
+            "spent_date": "2022-08-01",
+            "task": {"id": 10, "name": "Development"},
+            "notes": "Notes",
+            "is_running": true
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn should_pass_method_calls_to_the_application() {
+        let bus = match PrivateBus::start() {
+            Some(bus) => bus,
+            None => return,
+        };
+        let (to_test, requests) = mpsc::channel();
+        let _service = Service::start(Some(bus.address.clone()), move |request| {
+            to_test.send(request).unwrap();
+        })
+        .unwrap();
+
+        let client = Connection::new_address(&bus.address).unwrap();
+        let proxy = client.with_proxy(NAME, PATH, Duration::from_secs(5));
+        let () = proxy
+            .method_call(INTERFACE, "StartTimer", (1u32, 10u32, "Notes"))
+            .unwrap();
+        let () = proxy.method_call(INTERFACE, "StopRunning", ()).unwrap();
+        let () = proxy.method_call(INTERFACE, "ToggleLast", ()).unwrap();
+
+        assert_eq!(
+            vec![
+                Request::StartTimer(1, 10, "Notes".to_string()),
+                Request::StopRunning,
+                Request::ToggleLast
+            ],
+            requests.try_iter().collect::<Vec<Request>>()
+        );
+        let result: Result<(), dbus::Error> = proxy.method_call(INTERFACE, "StartTimer", (1u32,));
+        assert!(result.is_err());
+
+        let (xml,): (String,) = proxy
+            .method_call("org.freedesktop.DBus.Introspectable", "Introspect", ())
+            .unwrap();
+        assert!(xml.contains("GetRunning"));
+    }
+
+    #[test]
+    fn should_report_the_running_timer() {
+        let bus = match PrivateBus::start() {
+            Some(bus) => bus,
+            None => return,
+        };
+        let service = Service::start(Some(bus.address.clone()), |_| {}).unwrap();
+        assert!(Service::start(Some(bus.address.clone()), |_| {}).is_err());
+
+        let client = Connection::new_address(&bus.address).unwrap();
+        let (to_test, signals) = mpsc::channel();
+        for name in &["TimerStarted", "TimerStopped"] {
+            let to_test = to_test.clone();
+            client
+                .add_match(
+                    MatchRule::new_signal(INTERFACE, *name),
+                    move |(time_entry,): (PropMap,), _, message| {
+                        let member = message.member().unwrap().to_string();
+                        let id = time_entry["id"].as_u64().unwrap();
+                        to_test.send((member, id)).unwrap();
+                        true
+                    },
+                )
+                .unwrap();
+        }
+
+        let proxy = client.with_proxy(NAME, PATH, Duration::from_secs(5));
+        let (time_entry,): (PropMap,) = proxy.method_call(INTERFACE, "GetRunning", ()).unwrap();
+        assert!(time_entry.is_empty());
+
+        service.set_running(Some(&running()));
+        service.set_running(Some(&running()));
+        service.set_running(None);
+        let mut received = vec![];
+        while received.len() < 2 {
+            client.process(Duration::from_secs(5)).unwrap();
+            received.extend(signals.try_iter());
+        }
+        assert_eq!(
+            vec![
+                ("TimerStarted".to_string(), 7),
+                ("TimerStopped".to_string(), 7)
+            ],
+            received
+        );
+
+        service.set_running(Some(&running()));
+        let (time_entry,): (PropMap,) = proxy.method_call(INTERFACE, "GetRunning", ()).unwrap();
+        assert_eq!(Some("Development"), time_entry["task"].as_str());
+        assert_eq!(Some(1.5), time_entry["hours"].as_f64());
+    }
+}
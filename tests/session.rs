@@ -0,0 +1,142 @@
+mod private_bus;
+
+#[cfg(test)]
+mod test {
+    use crate::private_bus::PrivateBus;
+    use dbus::arg::{OwnedFd, PropMap, Variant};
+    use dbus::blocking::Connection;
+    use dbus::{Message, Path};
+    use std::io::Read;
+    use std::os::unix::io::IntoRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use timer_for_harvest::session::{Event, SessionMonitor};
+
+    const SESSION: &str = "/org/freedesktop/login1/session/_31";
+
+    /* answers like logind would, sends the signals it receives and the inhibitors it hands out */
+    fn fake_logind(bus: &PrivateBus) -> (mpsc::Sender<Message>, mpsc::Receiver<UnixStream>) {
+        let connection = Connection::new_address(&bus.address).unwrap();
+        connection
+            .request_name("org.freedesktop.login1", false, false, true)
+            .unwrap();
+        let (to_logind, signals) = mpsc::channel::<Message>();
+        let (to_test, inhibitors) = mpsc::channel();
+        thread::spawn(move || loop {
+            for signal in signals.try_iter() {
+                let _ = connection.channel().send(signal);
+            }
+            let message = match connection
+                .channel()
+                .blocking_pop_message(Duration::from_millis(20))
+            {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                Err(_) => return,
+            };
+            let reply = match message.member().map(|m| m.to_string()).as_deref() {
+                Some("Inhibit") => {
+                    let (held, handed_out) = UnixStream::pair().unwrap();
+                    let fd = unsafe { OwnedFd::new(handed_out.into_raw_fd()) };
+                    let _ = to_test.send(held);
+                    message.method_return().append1(fd)
+                }
+                Some("GetSessionByPID") => message.method_return().append1(Path::from(SESSION)),
+                _ => continue,
+            };
+            let _ = connection.channel().send(reply);
+        });
+        (to_logind, inhibitors)
+    }
+
+    fn prepare_for_sleep(start: bool) -> Message {
+        Message::signal(
+            &"/org/freedesktop/login1".into(),
+            &"org.freedesktop.login1.Manager".into(),
+            &"PrepareForSleep".into(),
+        )
+        .append1(start)
+    }
+
+    fn locked_hint(locked: bool) -> Message {
+        let mut changed = PropMap::new();
+        changed.insert("LockedHint".to_string(), Variant(Box::new(locked)));
+        Message::signal(
+            &SESSION.into(),
+            &"org.freedesktop.DBus.Properties".into(),
+            &"PropertiesChanged".into(),
+        )
+        .append3(
+            "org.freedesktop.login1.Session",
+            changed,
+            Vec::<String>::new(),
+        )
+    }
+
+    fn is_held(inhibitor: &mut UnixStream) -> bool {
+        inhibitor
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        inhibitor.read(&mut [0; 1]).is_err()
+    }
+
+    #[test]
+    fn should_report_the_time_asleep() {
+        let bus = match PrivateBus::start() {
+            Some(bus) => bus,
+            None => return,
+        };
+        let (to_logind, inhibitors) = fake_logind(&bus);
+        let (to_test, events) = mpsc::channel();
+        SessionMonitor::connect(Some(bus.address.clone()))
+            .unwrap()
+            .watch(move |event| to_test.send(event).unwrap());
+
+        let timeout = Duration::from_secs(5);
+        let mut inhibitor = inhibitors.recv_timeout(timeout).unwrap();
+        assert!(is_held(&mut inhibitor));
+
+        to_logind.send(prepare_for_sleep(true)).unwrap();
+        inhibitor.set_read_timeout(Some(timeout)).unwrap();
+        assert_eq!(0, inhibitor.read(&mut [0; 1]).unwrap());
+        assert!(events.try_recv().is_err());
+
+        thread::sleep(Duration::from_millis(100));
+        to_logind.send(prepare_for_sleep(false)).unwrap();
+        match events.recv_timeout(timeout).unwrap() {
+            Event::Resumed(away) => assert!(away.duration >= Duration::from_millis(100)),
+            event => panic!("Unexpected {:?}", event),
+        }
+        /* taken again for the next time */
+        assert!(is_held(&mut inhibitors.recv_timeout(timeout).unwrap()));
+    }
+
+    #[test]
+    fn should_report_screen_locks() {
+        let bus = match PrivateBus::start() {
+            Some(bus) => bus,
+            None => return,
+        };
+        let (to_logind, _inhibitors) = fake_logind(&bus);
+        let (to_test, events) = mpsc::channel();
+        SessionMonitor::connect(Some(bus.address.clone()))
+            .unwrap()
+            .watch(move |event| to_test.send(event).unwrap());
+
+        let timeout = Duration::from_secs(5);
+        to_logind.send(locked_hint(true)).unwrap();
+        assert_eq!(Event::Locked, events.recv_timeout(timeout).unwrap());
+
+        /* logind repeats the hint when other properties change */
+        to_logind.send(locked_hint(true)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        to_logind.send(locked_hint(false)).unwrap();
+        match events.recv_timeout(timeout).unwrap() {
+            Event::Unlocked(away) => assert!(away.duration >= Duration::from_millis(100)),
+            event => panic!("Unexpected {:?}", event),
+        }
+        assert!(events.try_recv().is_err());
+    }
+}
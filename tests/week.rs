@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+    use timer_for_harvest::week::Week;
+    use timer_for_harvest::TimeEntry;
+
+    fn time_entry(project_id: u32, spent_date: &str, hours: f32) -> TimeEntry {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "project": {"id": project_id, "name": format!("Project {}", project_id), "code": null, "client": null},
+            "client": {"id": 2, "name": "Client"},
+            "hours": hours,
+            "user": {"id": 3},
+            "spent_date": spent_date,
+            "task": {"id": 4, "name": "Development"},
+            "notes": null,
+            "is_running": false
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn should_start_weeks_on_monday() {
+        let monday = NaiveDate::from_ymd(2022, 8, 1);
+        let sunday = NaiveDate::from_ymd(2022, 8, 7);
+        assert_eq!(7, Week::days_of(monday).len());
+        assert_eq!(monday, Week::days_of(sunday)[0]);
+        assert_eq!(sunday, Week::days_of(NaiveDate::from_ymd(2022, 8, 3))[6]);
+    }
+
+    #[test]
+    fn should_sum_hours_per_project_and_day() {
+        let week = Week::new(
+            NaiveDate::from_ymd(2022, 8, 3),
+            &[
+                time_entry(2, "2022-08-01", 1.0),
+                time_entry(1, "2022-08-01", 2.0),
+                time_entry(1, "2022-08-01", 0.5),
+                time_entry(1, "2022-08-05", 3.0),
+                /* the week before */
+                time_entry(3, "2022-07-31", 8.0),
+            ],
+        );
+
+        assert_eq!(2, week.rows.len());
+        assert_eq!("Project 1", week.rows[0].project.name);
+        assert_eq!(vec![2.5, 0.0, 0.0, 0.0, 3.0, 0.0, 0.0], week.rows[0].hours);
+        assert_eq!(1.0, week.rows[1].hours[0]);
+        assert_eq!(3.5, week.day_total(0));
+        assert_eq!(0.0, week.day_total(1));
+        assert_eq!(6.5, week.total());
+    }
+}